@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS events (
+    position BIGSERIAL PRIMARY KEY,
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    sequence BIGINT NOT NULL,
+    event_type TEXT NOT NULL,
+    event_version INTEGER NOT NULL,
+    payload BYTEA NOT NULL,
+    metadata JSONB NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (aggregate_type, aggregate_id, sequence)
+);
+
+CREATE OR REPLACE FUNCTION notify_event_appended() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('events', NEW.aggregate_type);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS event_appended ON events;
+CREATE TRIGGER event_appended AFTER INSERT ON events
+    FOR EACH ROW EXECUTE FUNCTION notify_event_appended();
@@ -0,0 +1,183 @@
+use crate::eventstore::{Position, Version};
+use crate::serialization::{
+    EventPayload, EventRegistry, Format, SerializationResult, SerializedEvent,
+};
+use crate::{Aggregate, AggregateEvent, Event};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const CAUSATION_ID: &str = "causation_id";
+pub const CORRELATION_ID: &str = "correlation_id";
+pub const USER_ID: &str = "user_id";
+
+/// Free-form key/value pairs recorded together with events, e.g. who or what caused them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    pub fn new() -> Metadata {
+        Metadata(BTreeMap::new())
+    }
+
+    pub fn with(mut self, key: &str, value: &str) -> Metadata {
+        self.insert(key, value);
+        self
+    }
+
+    pub fn with_causation_id(self, value: &str) -> Metadata {
+        self.with(CAUSATION_ID, value)
+    }
+
+    pub fn with_correlation_id(self, value: &str) -> Metadata {
+        self.with(CORRELATION_ID, value)
+    }
+
+    pub fn with_user_id(self, value: &str) -> Metadata {
+        self.with(USER_ID, value)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) -> Option<String> {
+        self.0.insert(key.to_owned(), value.to_owned())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn causation_id(&self) -> Option<&str> {
+        self.get(CAUSATION_ID)
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.get(CORRELATION_ID)
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.get(USER_ID)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A committed event together with the stream it belongs to and its position in that stream and
+/// in the whole store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventEnvelope<E: Event> {
+    pub aggregate_type: &'static str,
+    pub aggregate_id: String,
+    /// Version of the stream once this event got applied, the first event has sequence 1.
+    pub sequence: Version,
+    pub position: Position,
+    pub recorded_at: DateTime<Utc>,
+    pub metadata: Metadata,
+    pub event: E,
+}
+
+impl<E: Event> EventEnvelope<E> {
+    pub fn new<A: Aggregate>(
+        aggregate_id: String,
+        sequence: Version,
+        position: Position,
+        event: E,
+        metadata: Metadata,
+    ) -> EventEnvelope<E> {
+        EventEnvelope {
+            aggregate_type: A::aggregate_type(),
+            aggregate_id,
+            sequence,
+            position,
+            recorded_at: Utc::now(),
+            metadata,
+            event,
+        }
+    }
+
+    pub fn event_type(&self) -> &'static str {
+        self.event.event_type()
+    }
+}
+
+/// A committed event of any aggregate type as it is stored, e.g. read from a log the stores of
+/// several aggregate types share.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEvent {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: Version,
+    pub position: Position,
+    pub recorded_at: DateTime<Utc>,
+    pub metadata: Metadata,
+    pub event: SerializedEvent,
+}
+
+impl RecordedEvent {
+    /// Decodes the event with the registry of its aggregate type `A`.
+    pub fn decode<A, E, F>(
+        self,
+        registry: &EventRegistry<E, F>,
+    ) -> SerializationResult<EventEnvelope<E>>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A> + EventPayload,
+        F: Format,
+    {
+        Ok(EventEnvelope {
+            aggregate_type: A::aggregate_type(),
+            aggregate_id: self.aggregate_id,
+            sequence: self.sequence,
+            position: self.position,
+            recorded_at: self.recorded_at,
+            metadata: self.metadata,
+            event: registry.deserialize(&self.event)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::{EventEnvelope, Metadata};
+    use crate::test_support::{Counter, CounterEvent};
+
+    #[test]
+    fn envelope_carries_aggregate_type_and_stream() {
+        // Act
+        let envelope = EventEnvelope::new::<Counter>(
+            "100".into(),
+            3,
+            7,
+            CounterEvent::Added(1),
+            Metadata::new(),
+        );
+
+        // Assert
+        assert_eq!("Counter", envelope.aggregate_type);
+        assert_eq!("100", envelope.aggregate_id);
+        assert_eq!(3, envelope.sequence);
+        assert_eq!(7, envelope.position);
+        assert_eq!("added", envelope.event_type());
+    }
+
+    #[test]
+    fn metadata_keeps_well_known_keys() {
+        // Act
+        let metadata = Metadata::new()
+            .with_causation_id("cmd-1")
+            .with_correlation_id("request-7")
+            .with_user_id("miro")
+            .with("ip", "127.0.0.1");
+
+        // Assert
+        assert_eq!(Some("cmd-1"), metadata.causation_id());
+        assert_eq!(Some("request-7"), metadata.correlation_id());
+        assert_eq!(Some("miro"), metadata.user_id());
+        assert_eq!(Some("127.0.0.1"), metadata.get("ip"));
+        assert_eq!(None, metadata.get("unknown"));
+    }
+}
@@ -1,5 +0,0 @@
-pub trait EventStore {}
-
-pub struct DummyEventStore {}
-
-impl EventStore for DummyEventStore {}
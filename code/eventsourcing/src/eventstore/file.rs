@@ -0,0 +1,595 @@
+use super::{
+    AppendResult, EventStore, EventStoreError, ExpectedVersion, LoadResult, Position, Version,
+};
+use crate::envelope::{EventEnvelope, Metadata};
+use crate::serialization::{Binary, EventPayload, EventRegistry, Format, SerializationError};
+use crate::{Aggregate, AggregateEvent};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{error, fmt};
+
+/// Every record starts with the length of its body, the CRC32 of that length and the CRC32 of the
+/// body. A damaged length can not pass for a record running past the end of the file that way.
+const HEADER_LEN: u64 = 12;
+
+/// Durable store writing all streams of the aggregate `A` into a single append-only segment file
+/// `<aggregate_type>.log`, events are encoded by the registry in the format `F`.
+///
+/// Every append is fsynced before it is acknowledged. A record torn by a crash in the middle of
+/// an append is the last one in the file and gets truncated the next time the store is opened,
+/// any other record failing a checksum makes opening the store fail instead of dropping the
+/// history after it.
+pub struct FileEventStore<A, E, F> {
+    path: PathBuf,
+    registry: EventRegistry<E, F>,
+    segment: Mutex<Segment>,
+    _aggregate: PhantomData<A>,
+}
+
+struct Segment {
+    file: File,
+    len: u64,
+    /// Offsets of all records in the order they were appended, the record at position `n` starts
+    /// at `offsets[n - 1]`.
+    offsets: Vec<u64>,
+    /// Positions of records of every stream, in the order they were appended.
+    streams: HashMap<String, Vec<Position>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileRecord {
+    aggregate_id: String,
+    sequence: Version,
+    recorded_at: DateTime<Utc>,
+    metadata: Metadata,
+    event: Vec<u8>,
+}
+
+impl<A, E, F> FileEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    /// Opens the segment in `dir`, creating both if needed, and indexes the records it holds.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        registry: EventRegistry<E, F>,
+    ) -> Result<FileEventStore<A, E, F>, FileStoreError> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(format!("{}.log", A::aggregate_type()));
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        // makes the entry of a just created segment survive a crash as well
+        File::open(&dir)?.sync_all()?;
+
+        Ok(FileEventStore {
+            path,
+            registry,
+            segment: Mutex::new(Segment::recover(file)?),
+            _aggregate: PhantomData,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Segment {
+    /// Indexes all records, cutting off the last one if a crash left it incomplete.
+    fn recover(mut file: File) -> Result<Segment, FileStoreError> {
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut bytes)?;
+
+        let mut offsets = Vec::new();
+        let mut streams: HashMap<String, Vec<Position>> = HashMap::new();
+        let mut offset = 0;
+        while offset < bytes.len() as u64 {
+            let (record, len) = match decode_record(&bytes[offset as usize..]) {
+                Decoded::Record(record, len) => (record, len),
+                Decoded::Incomplete => break,
+                Decoded::Corrupt => return Err(FileStoreError::Corrupt { offset }),
+            };
+            offsets.push(offset);
+            streams
+                .entry(record.aggregate_id)
+                .or_default()
+                .push(offsets.len() as Position);
+            offset += len;
+        }
+
+        if offset < bytes.len() as u64 {
+            file.set_len(offset)?;
+            file.sync_all()?;
+        }
+
+        Ok(Segment {
+            file,
+            len: offset,
+            offsets,
+            streams,
+        })
+    }
+
+    fn read(&mut self, offset: u64) -> Result<FileRecord, FileStoreError> {
+        let mut header = [0; HEADER_LEN as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut header)?;
+
+        let mut record = header.to_vec();
+        record.resize((HEADER_LEN + body_len(&header)) as usize, 0);
+        self.file.read_exact(&mut record[HEADER_LEN as usize..])?;
+
+        match decode_record(&record) {
+            Decoded::Record(record, _) => Ok(record),
+            _ => Err(FileStoreError::Corrupt { offset }),
+        }
+    }
+
+    /// Writes all records at once, rolling the file back if the write or the fsync fails so a
+    /// half written append can never end up in front of later ones.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let result = self
+            .file
+            .write_all(bytes)
+            .and_then(|_| self.file.sync_data());
+
+        if result.is_err() {
+            let _ = self.file.set_len(self.len);
+        }
+        result
+    }
+}
+
+fn body_len(header: &[u8]) -> u64 {
+    u64::from(u32::from_le_bytes([
+        header[0], header[1], header[2], header[3],
+    ]))
+}
+
+enum Decoded {
+    /// The record with its length on disk.
+    Record(FileRecord, u64),
+    /// An intact header announcing more bytes than are left, a header cut short or nothing but
+    /// the zeros of a file grown without its data. Only an append torn by a crash leaves the last
+    /// record like that, nothing intact follows it.
+    Incomplete,
+    /// Fails a checksum or can not be decoded.
+    Corrupt,
+}
+
+/// Decodes the record at the start of `bytes`.
+fn decode_record(bytes: &[u8]) -> Decoded {
+    if (bytes.len() as u64) < HEADER_LEN {
+        return Decoded::Incomplete;
+    }
+    if crc32fast::hash(&bytes[0..4]) != checksum_at(bytes, 4) {
+        if bytes.iter().all(|&byte| byte == 0) {
+            return Decoded::Incomplete;
+        }
+        return Decoded::Corrupt;
+    }
+    let len = HEADER_LEN + body_len(bytes);
+    if (bytes.len() as u64) < len {
+        return Decoded::Incomplete;
+    }
+
+    let body = &bytes[HEADER_LEN as usize..len as usize];
+    if crc32fast::hash(body) != checksum_at(bytes, 8) {
+        return Decoded::Corrupt;
+    }
+
+    match Binary::decode(body) {
+        Ok(record) => Decoded::Record(record, len),
+        Err(_) => Decoded::Corrupt,
+    }
+}
+
+fn checksum_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn encode_record(record: &FileRecord) -> Result<Vec<u8>, FileStoreError> {
+    let body = Binary::encode(record)?;
+    let len = (body.len() as u32).to_le_bytes();
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN as usize + body.len());
+    bytes.extend_from_slice(&len);
+    bytes.extend_from_slice(&crc32fast::hash(&len).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+impl<A, E, F> FileEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    fn read_envelopes(
+        &self,
+        segment: &mut Segment,
+        positions: Vec<Position>,
+    ) -> LoadResult<E, FileStoreError> {
+        let mut envelopes = Vec::with_capacity(positions.len());
+        for position in positions {
+            let offset = segment.offsets[position as usize - 1];
+            let record = segment.read(offset).map_err(EventStoreError::Store)?;
+            let event = self
+                .registry
+                .from_bytes(&record.event)
+                .map_err(|err| EventStoreError::Store(err.into()))?;
+
+            envelopes.push(EventEnvelope {
+                aggregate_type: A::aggregate_type(),
+                aggregate_id: record.aggregate_id,
+                sequence: record.sequence,
+                position,
+                recorded_at: record.recorded_at,
+                metadata: record.metadata,
+                event,
+            });
+        }
+
+        Ok(envelopes)
+    }
+}
+
+impl<A, E, F> EventStore<A> for FileEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    type Event = E;
+    type Error = FileStoreError;
+
+    fn load(&self, aggregate_id: &A::Id) -> LoadResult<E, Self::Error> {
+        self.load_from(aggregate_id, 0)
+    }
+
+    fn load_from(&self, aggregate_id: &A::Id, after: Version) -> LoadResult<E, Self::Error> {
+        let mut segment = self.segment.lock().unwrap();
+        let positions: Vec<Position> = match segment.streams.get(&aggregate_id.to_string()) {
+            Some(positions) => positions.iter().skip(after as usize).cloned().collect(),
+            None => return Ok(Vec::new()),
+        };
+
+        self.read_envelopes(&mut segment, positions)
+    }
+
+    fn read_all(&self, after: Position, limit: usize) -> LoadResult<E, Self::Error> {
+        let mut segment = self.segment.lock().unwrap();
+        let last = segment.offsets.len() as Position;
+        let positions = (after + 1..=last).take(limit).collect();
+
+        self.read_envelopes(&mut segment, positions)
+    }
+
+    fn append(
+        &self,
+        aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[E],
+        metadata: &Metadata,
+    ) -> AppendResult<Self::Error> {
+        let aggregate_id = aggregate_id.to_string();
+        let mut segment = self.segment.lock().unwrap();
+        let version = segment.streams.get(&aggregate_id).map_or(0, Vec::len) as Version;
+
+        expected_version.check(version)?;
+
+        let mut bytes = Vec::new();
+        let mut offsets = Vec::with_capacity(events.len());
+        let first_position = segment.offsets.len() as Position + 1;
+        for (sequence, event) in (version + 1..).zip(events) {
+            let record = FileRecord {
+                aggregate_id: aggregate_id.clone(),
+                sequence,
+                recorded_at: Utc::now(),
+                metadata: metadata.clone(),
+                event: self
+                    .registry
+                    .to_bytes(event)
+                    .map_err(|err| EventStoreError::Store(err.into()))?,
+            };
+            offsets.push(segment.len + bytes.len() as u64);
+            bytes.extend(encode_record(&record).map_err(EventStoreError::Store)?);
+        }
+
+        segment
+            .write(&bytes)
+            .map_err(|err| EventStoreError::Store(err.into()))?;
+        segment.len += bytes.len() as u64;
+        segment.offsets.extend(offsets);
+        segment
+            .streams
+            .entry(aggregate_id)
+            .or_default()
+            .extend(first_position..first_position + events.len() as Position);
+
+        Ok(version + events.len() as Version)
+    }
+}
+
+#[derive(Debug)]
+pub enum FileStoreError {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// The record at the offset fails its checksum or can not be decoded.
+    Corrupt {
+        offset: u64,
+    },
+}
+
+impl From<io::Error> for FileStoreError {
+    fn from(err: io::Error) -> Self {
+        FileStoreError::Io(err)
+    }
+}
+
+impl From<SerializationError> for FileStoreError {
+    fn from(err: SerializationError) -> Self {
+        FileStoreError::Serialization(err)
+    }
+}
+
+impl fmt::Display for FileStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileStoreError::Io(err) => write!(f, "segment file can not be accessed: {}", err),
+            FileStoreError::Serialization(err) => err.fmt(f),
+            FileStoreError::Corrupt { offset } => {
+                write!(f, "segment file is corrupt at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl error::Error for FileStoreError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::Metadata;
+    use crate::eventstore::{
+        EventStore, EventStoreError, ExpectedVersion, FileEventStore, FileStoreError,
+        VersionConflict,
+    };
+    use crate::serialization::{EventRegistry, Json};
+    use crate::test_support::{events_of, Counter, CounterEvent, Reset};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    type TestEventStore = FileEventStore<Counter, CounterEvent, Json>;
+
+    fn open(dir: &TempDir) -> TestEventStore {
+        let registry = EventRegistry::new()
+            .register("added", 1, CounterEvent::Added)
+            .register("subtracted", 1, CounterEvent::Subtracted)
+            .register("reset", 2, CounterEvent::Reset);
+
+        FileEventStore::open(dir.path(), registry).unwrap()
+    }
+
+    #[test]
+    fn events_are_loaded_after_reopening() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let metadata = Metadata::new().with_user_id("miro");
+        let events = vec![
+            CounterEvent::Added(5),
+            CounterEvent::Reset(Reset {
+                reason: "audit".into(),
+            }),
+        ];
+        open(&dir)
+            .append(&100, ExpectedVersion::Exact(0), &events, &metadata)
+            .unwrap();
+        append(&open(&dir), 101, &[CounterEvent::Added(1)]);
+
+        // Act
+        let result = open(&dir).load(&100).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![1, 2],
+            result.iter().map(|e| e.sequence).collect::<Vec<_>>()
+        );
+        assert_eq!("Counter", result[0].aggregate_type);
+        assert_eq!(metadata, result[1].metadata);
+        assert_eq!(events, events_of(result));
+    }
+
+    #[test]
+    fn read_all_returns_events_after_position_across_reopening() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        drop(event_store);
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(3)]);
+
+        // Act
+        let result = event_store.read_all(1, 10).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![2, 3],
+            result.iter().map(|e| e.position).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![CounterEvent::Added(2), CounterEvent::Added(3)],
+            events_of(result)
+        );
+        assert_eq!(1, event_store.read_all(0, 1).unwrap().len());
+        assert_eq!(3, event_store.load(&100).unwrap()[1].position);
+    }
+
+    #[test]
+    fn append_on_stale_version_is_rejected() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        let expected = VersionConflict {
+            expected: 0,
+            actual: 1,
+        };
+
+        // Act
+        let result = event_store.append(
+            &100,
+            ExpectedVersion::Exact(0),
+            &[CounterEvent::Added(2)],
+            &Metadata::new(),
+        );
+
+        // Assert
+        match result {
+            Err(EventStoreError::Conflict(conflict)) => assert_eq!(expected, conflict),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn torn_trailing_record_is_truncated_on_open() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let intact_len = fs::metadata(event_store.path()).unwrap().len();
+        append(&event_store, 100, &[CounterEvent::Added(7)]);
+        let path = event_store.path().to_owned();
+        drop(event_store);
+        simulate_crash_during_last_append(&path, intact_len);
+
+        // Act
+        let event_store = open(&dir);
+
+        // Assert
+        assert_eq!(intact_len, fs::metadata(&path).unwrap().len());
+        assert_eq!(
+            vec![CounterEvent::Added(5)],
+            events_of(event_store.load(&100).unwrap())
+        );
+        let next = event_store.append(
+            &100,
+            ExpectedVersion::Exact(1),
+            &[CounterEvent::Added(3)],
+            &Metadata::new(),
+        );
+        assert_eq!(2, next.unwrap());
+    }
+
+    #[test]
+    fn record_with_bad_checksum_fails_opening() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let second = fs::metadata(event_store.path()).unwrap().len();
+        append(&event_store, 100, &[CounterEvent::Added(7)]);
+        append(&event_store, 100, &[CounterEvent::Added(9)]);
+        let path = event_store.path().to_owned();
+        drop(event_store);
+        let mut bytes = fs::read(&path).unwrap();
+        let len = bytes.len();
+        bytes[second as usize + 10] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        // Act
+        let result = TestEventStore::open(dir.path(), EventRegistry::new());
+
+        // Assert
+        match result {
+            Err(FileStoreError::Corrupt { offset }) => assert_eq!(second, offset),
+            Err(other) => panic!("expected corruption, got {:?}", other),
+            Ok(_) => panic!("expected corruption, the store got opened"),
+        }
+        assert_eq!(len as u64, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn record_with_damaged_length_fails_opening() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let second = fs::metadata(event_store.path()).unwrap().len();
+        append(&event_store, 100, &[CounterEvent::Added(7)]);
+        append(&event_store, 100, &[CounterEvent::Added(9)]);
+        let path = event_store.path().to_owned();
+        drop(event_store);
+        let mut bytes = fs::read(&path).unwrap();
+        let len = bytes.len();
+        // the length now runs past the end of the file, as a torn last record would
+        bytes[second as usize + 2] = 0x7f;
+        fs::write(&path, bytes).unwrap();
+
+        // Act
+        let result = TestEventStore::open(dir.path(), EventRegistry::new());
+
+        // Assert
+        match result {
+            Err(FileStoreError::Corrupt { offset }) => assert_eq!(second, offset),
+            Err(other) => panic!("expected corruption, got {:?}", other),
+            Ok(_) => panic!("expected corruption, the store got opened"),
+        }
+        assert_eq!(len as u64, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn zeroed_trailing_record_is_truncated_on_open() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let event_store = open(&dir);
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let intact_len = fs::metadata(event_store.path()).unwrap().len();
+        let path = event_store.path().to_owned();
+        drop(event_store);
+        // the file grew but the data of the append never made it to the disk
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(intact_len + 40).unwrap();
+        drop(file);
+
+        // Act
+        let event_store = open(&dir);
+
+        // Assert
+        assert_eq!(intact_len, fs::metadata(&path).unwrap().len());
+        assert_eq!(
+            vec![CounterEvent::Added(5)],
+            events_of(event_store.load(&100).unwrap())
+        );
+    }
+
+    /// Leaves only a part of the last record behind, as `kill -9` in the middle of a write would.
+    fn simulate_crash_during_last_append(path: &std::path::Path, intact_len: u64) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len(intact_len + 5).unwrap();
+        drop(file);
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(&[0xde, 0xad]).unwrap();
+    }
+
+    fn append(event_store: &TestEventStore, id: u64, events: &[CounterEvent]) {
+        event_store
+            .append(&id, ExpectedVersion::Any, events, &Metadata::new())
+            .unwrap();
+    }
+}
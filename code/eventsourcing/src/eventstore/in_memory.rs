@@ -0,0 +1,316 @@
+use super::{AppendResult, EventStore, ExpectedVersion, LoadResult, Position, Version};
+use crate::envelope::{EventEnvelope, Metadata};
+use crate::{Aggregate, AggregateEvent, Event};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::Duration;
+
+/// Keeps every stream of the aggregate `A` in memory, keyed by aggregate id.
+pub struct InMemoryEventStore<A, E: Event> {
+    log: RwLock<Log<E>>,
+    /// Number of appends so far, waiting subscribers get woken up whenever it changes.
+    appends: Mutex<u64>,
+    appended: Condvar,
+    _aggregate: PhantomData<A>,
+}
+
+/// All events in the order they were appended, the event at position `n` is at index `n - 1`.
+struct Log<E: Event> {
+    events: Vec<EventEnvelope<E>>,
+    streams: HashMap<String, Vec<usize>>,
+}
+
+impl<A, E: Event> InMemoryEventStore<A, E> {
+    pub fn new() -> InMemoryEventStore<A, E> {
+        InMemoryEventStore {
+            log: RwLock::new(Log {
+                events: Vec::new(),
+                streams: HashMap::new(),
+            }),
+            appends: Mutex::new(0),
+            appended: Condvar::new(),
+            _aggregate: PhantomData,
+        }
+    }
+}
+
+impl<A, E: Event> Default for InMemoryEventStore<A, E> {
+    fn default() -> Self {
+        InMemoryEventStore::new()
+    }
+}
+
+impl<A, E> EventStore<A> for InMemoryEventStore<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+{
+    type Event = E;
+    type Error = Infallible;
+
+    fn load(&self, aggregate_id: &A::Id) -> LoadResult<E, Self::Error> {
+        self.load_from(aggregate_id, 0)
+    }
+
+    fn load_from(&self, aggregate_id: &A::Id, after: Version) -> LoadResult<E, Self::Error> {
+        let log = self.log.read().unwrap();
+
+        match log.streams.get(&aggregate_id.to_string()) {
+            Some(stream) => Ok(stream
+                .iter()
+                .skip(after as usize)
+                .map(|&index| log.events[index].clone())
+                .collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn read_all(&self, after: Position, limit: usize) -> LoadResult<E, Self::Error> {
+        let log = self.log.read().unwrap();
+
+        Ok(log
+            .events
+            .iter()
+            .skip(after as usize)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn append(
+        &self,
+        aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[E],
+        metadata: &Metadata,
+    ) -> AppendResult<Self::Error> {
+        let version = {
+            let mut log = self.log.write().unwrap();
+            let Log {
+                events: ref mut all,
+                ref mut streams,
+            } = *log;
+            let aggregate_id = aggregate_id.to_string();
+            let version = streams
+                .get(&aggregate_id)
+                .map_or(0, |stream| stream.len() as Version);
+
+            // a rejected or empty append leaves no empty stream behind
+            expected_version.check(version)?;
+            if events.is_empty() {
+                return Ok(version);
+            }
+
+            let stream = streams.entry(aggregate_id.clone()).or_default();
+            for event in events {
+                stream.push(all.len());
+                all.push(EventEnvelope::new::<A>(
+                    aggregate_id.clone(),
+                    stream.len() as Version,
+                    all.len() as Position + 1,
+                    event.clone(),
+                    metadata.clone(),
+                ));
+            }
+
+            stream.len() as Version
+        };
+
+        *self.appends.lock().unwrap() += 1;
+        self.appended.notify_all();
+
+        Ok(version)
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        let appends = self.appends.lock().unwrap();
+        let seen = *appends;
+
+        let _ = self
+            .appended
+            .wait_timeout_while(appends, timeout, |appends| *appends == seen)
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::Metadata;
+    use crate::eventstore::{
+        EventStore, EventStoreError, ExpectedVersion, InMemoryEventStore, VersionConflict,
+    };
+    use crate::test_support::{events_of, Counter, CounterEvent};
+
+    type TestEventStore = InMemoryEventStore<Counter, CounterEvent>;
+
+    #[test]
+    fn load_returns_only_events_of_requested_stream() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+
+        // Act
+        let result = event_store.load(&100).unwrap();
+
+        // Assert
+        assert_eq!(vec![CounterEvent::Added(1)], events_of(result));
+    }
+
+    #[test]
+    fn loading_unknown_stream_returns_no_events() {
+        let event_store = TestEventStore::new();
+
+        assert_eq!(Ok(vec![]), event_store.load(&100));
+    }
+
+    #[test]
+    fn append_returns_version_of_the_stream() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        let events = vec![CounterEvent::Added(2), CounterEvent::Subtracted(1)];
+        let metadata = Metadata::new();
+
+        // Act
+        let first = event_store.append(&100, ExpectedVersion::Exact(0), &events, &metadata);
+        let second = event_store.append(&100, ExpectedVersion::Exact(2), &events, &metadata);
+        let other_stream = event_store.append(&101, ExpectedVersion::Any, &events, &metadata);
+
+        // Assert
+        assert_eq!(Ok(2), first);
+        assert_eq!(Ok(4), second);
+        assert_eq!(Ok(2), other_stream);
+    }
+
+    #[test]
+    fn loaded_events_are_wrapped_in_envelopes() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        let metadata = Metadata::new().with_user_id("miro");
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        event_store
+            .append(
+                &100,
+                ExpectedVersion::Exact(1),
+                &[CounterEvent::Added(2)],
+                &metadata,
+            )
+            .unwrap();
+
+        // Act
+        let result = event_store.load(&100).unwrap();
+
+        // Assert
+        let last = &result[1];
+        assert_eq!("Counter", last.aggregate_type);
+        assert_eq!("100", last.aggregate_id);
+        assert_eq!(
+            vec![1, 2],
+            result.iter().map(|e| e.sequence).collect::<Vec<_>>()
+        );
+        assert_eq!(metadata, last.metadata);
+        assert_eq!(CounterEvent::Added(2), last.event);
+    }
+
+    #[test]
+    fn load_from_skips_events_up_to_given_version() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        let events = vec![
+            CounterEvent::Added(1),
+            CounterEvent::Added(2),
+            CounterEvent::Added(3),
+        ];
+        append(&event_store, 100, &events);
+
+        // Act
+        let result = event_store.load_from(&100, 2).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![3],
+            result.iter().map(|e| e.sequence).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![CounterEvent::Added(3)], events_of(result));
+    }
+
+    #[test]
+    fn read_all_pages_through_all_streams_by_position() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(3), CounterEvent::Added(4)],
+        );
+
+        // Act
+        let first_page = event_store.read_all(0, 3).unwrap();
+        let second_page = event_store.read_all(3, 3).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![(1, "100", 1), (2, "101", 1), (3, "100", 2)],
+            first_page
+                .iter()
+                .map(|e| (e.position, e.aggregate_id.as_str(), e.sequence))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![CounterEvent::Added(4)], events_of(second_page));
+        assert_eq!(Ok(vec![]), event_store.read_all(4, 3));
+    }
+
+    #[test]
+    fn append_on_stale_version_is_rejected() {
+        // Arrange
+        let event_store = TestEventStore::new();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        let expected = Err(EventStoreError::Conflict(VersionConflict {
+            expected: 0,
+            actual: 1,
+        }));
+
+        // Act
+        let result = event_store.append(
+            &100,
+            ExpectedVersion::Exact(0),
+            &[CounterEvent::Added(2)],
+            &Metadata::new(),
+        );
+
+        // Assert
+        assert_eq!(expected, result);
+        assert_eq!(
+            vec![CounterEvent::Added(1)],
+            events_of(event_store.load(&100).unwrap())
+        );
+    }
+
+    #[test]
+    fn rejected_append_to_unknown_stream_leaves_no_stream_behind() {
+        // Arrange
+        let event_store = TestEventStore::new();
+
+        // Act
+        let result = event_store.append(
+            &100,
+            ExpectedVersion::Exact(1),
+            &[CounterEvent::Added(1)],
+            &Metadata::new(),
+        );
+
+        // Assert
+        assert!(result.is_err());
+        assert!(event_store.log.read().unwrap().streams.is_empty());
+    }
+
+    fn append(event_store: &TestEventStore, id: u64, events: &[CounterEvent]) {
+        event_store
+            .append(&id, ExpectedVersion::Any, events, &Metadata::new())
+            .unwrap();
+    }
+}
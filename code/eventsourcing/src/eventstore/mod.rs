@@ -0,0 +1,280 @@
+use crate::envelope::{EventEnvelope, Metadata, RecordedEvent};
+use crate::{Aggregate, AggregateEvent, CqrsError};
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{error, fmt, thread};
+
+mod file;
+mod in_memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::file::{FileEventStore, FileStoreError};
+pub use self::in_memory::InMemoryEventStore;
+#[cfg(feature = "postgres")]
+pub use self::postgres::{PostgresEventStore, PostgresStoreError, PostgresSubscription};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::{SqliteEventStore, SqliteStoreError};
+
+/// Number of events committed to a stream, a stream that was never written to is at version 0.
+pub type Version = u64;
+
+/// Place of an event among all events of the store, increasing with every appended event. The
+/// first event is at position 1, so a consumer which has seen nothing yet is at position 0.
+pub type Position = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedVersion {
+    /// Append no matter how far the stream has moved on.
+    Any,
+    /// Append only if the stream is still at the given version.
+    Exact(Version),
+}
+
+impl ExpectedVersion {
+    pub fn check(self, actual: Version) -> Result<(), VersionConflict> {
+        match self {
+            ExpectedVersion::Exact(expected) if expected != actual => {
+                Err(VersionConflict { expected, actual })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub expected: Version,
+    pub actual: Version,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventStoreError<E> {
+    Conflict(VersionConflict),
+    Store(E),
+}
+
+impl<E> From<VersionConflict> for EventStoreError<E> {
+    fn from(conflict: VersionConflict) -> Self {
+        EventStoreError::Conflict(conflict)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for EventStoreError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventStoreError::Conflict(conflict) => write!(
+                f,
+                "stream moved on, expected version {} but found {}",
+                conflict.expected, conflict.actual
+            ),
+            EventStoreError::Store(err) => write!(f, "event store failed: {}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for EventStoreError<E> {}
+
+pub type LoadResult<E, Err> = Result<Vec<EventEnvelope<E>>, EventStoreError<Err>>;
+pub type AppendResult<Err> = Result<Version, EventStoreError<Err>>;
+pub type LogResult<Err> = Result<Vec<RecordedEvent>, EventStoreError<Err>>;
+
+pub trait EventStore<A: Aggregate> {
+    type Event: AggregateEvent<A>;
+    type Error: CqrsError;
+
+    /// Loads all events of the given stream in the order they were appended.
+    fn load(&self, aggregate_id: &A::Id) -> LoadResult<Self::Event, Self::Error>;
+
+    /// Loads events of the given stream appended after the stream reached version `after`,
+    /// e.g. the ones not covered by a snapshot yet.
+    fn load_from(
+        &self,
+        aggregate_id: &A::Id,
+        after: Version,
+    ) -> LoadResult<Self::Event, Self::Error> {
+        let mut envelopes = self.load(aggregate_id)?;
+        envelopes.retain(|envelope| envelope.sequence > after);
+        Ok(envelopes)
+    }
+
+    /// Loads up to `limit` events of all streams of `A` appended after the event at position
+    /// `after`, in the order of their positions.
+    fn read_all(&self, after: Position, limit: usize) -> LoadResult<Self::Event, Self::Error>;
+
+    /// Appends events to the stream, recording the metadata with each of them, and returns the
+    /// new stream version.
+    fn append(
+        &self,
+        aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[Self::Event],
+        metadata: &Metadata,
+    ) -> AppendResult<Self::Error>;
+
+    /// Blocks until events may have been appended or the timeout passed, stores which can not
+    /// tell when that happens sleep through the whole timeout.
+    fn wait_for_append(&self, timeout: Duration) {
+        thread::sleep(timeout);
+    }
+}
+
+/// Log the stores of several aggregate types share, e.g. the `events` table of one database, the
+/// positions of events are global across all of them.
+///
+/// The in-memory store keeps the decoded events of a single aggregate type and is no log, follow
+/// it by aggregate type instead.
+pub trait EventLog {
+    type Error: CqrsError;
+
+    /// Loads up to `limit` events of every aggregate type appended after the event at position
+    /// `after`, in the order of their positions. They stay encoded as only the registry of their
+    /// aggregate type can decode them.
+    fn read_log(&self, after: Position, limit: usize) -> LogResult<Self::Error>;
+
+    /// Blocks until events of any aggregate type may have been appended or the timeout passed.
+    fn wait_for_append(&self, timeout: Duration);
+}
+
+impl<L: EventLog> EventLog for Arc<L> {
+    type Error = L::Error;
+
+    fn read_log(&self, after: Position, limit: usize) -> LogResult<Self::Error> {
+        (**self).read_log(after, limit)
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        (**self).wait_for_append(timeout)
+    }
+}
+
+/// Lets a repository and projection runners share one store.
+impl<A, S> EventStore<A> for Arc<S>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    type Event = S::Event;
+    type Error = S::Error;
+
+    fn load(&self, aggregate_id: &A::Id) -> LoadResult<Self::Event, Self::Error> {
+        (**self).load(aggregate_id)
+    }
+
+    fn load_from(
+        &self,
+        aggregate_id: &A::Id,
+        after: Version,
+    ) -> LoadResult<Self::Event, Self::Error> {
+        (**self).load_from(aggregate_id, after)
+    }
+
+    fn read_all(&self, after: Position, limit: usize) -> LoadResult<Self::Event, Self::Error> {
+        (**self).read_all(after, limit)
+    }
+
+    fn append(
+        &self,
+        aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[Self::Event],
+        metadata: &Metadata,
+    ) -> AppendResult<Self::Error> {
+        (**self).append(aggregate_id, expected_version, events, metadata)
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        (**self).wait_for_append(timeout)
+    }
+}
+
+pub struct DummyEventStore<E> {
+    _event: PhantomData<E>,
+}
+
+impl<E> DummyEventStore<E> {
+    pub fn new() -> DummyEventStore<E> {
+        DummyEventStore {
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<E> Default for DummyEventStore<E> {
+    fn default() -> Self {
+        DummyEventStore::new()
+    }
+}
+
+impl<A, E> EventStore<A> for DummyEventStore<E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    type Event = E;
+    type Error = Infallible;
+
+    fn load(&self, _aggregate_id: &A::Id) -> LoadResult<E, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    fn read_all(&self, _after: Position, _limit: usize) -> LoadResult<E, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    fn append(
+        &self,
+        _aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[E],
+        _metadata: &Metadata,
+    ) -> AppendResult<Self::Error> {
+        expected_version.check(0)?;
+        Ok(events.len() as Version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eventstore::{EventStoreError, ExpectedVersion, VersionConflict};
+
+    #[test]
+    fn any_version_never_conflicts() {
+        assert_eq!(Ok(()), ExpectedVersion::Any.check(0));
+        assert_eq!(Ok(()), ExpectedVersion::Any.check(42));
+    }
+
+    #[test]
+    fn exact_version_conflicts_when_stream_moved_on() {
+        // Arrange
+        let expected = Err(VersionConflict {
+            expected: 2,
+            actual: 3,
+        });
+
+        // Act
+        let result = ExpectedVersion::Exact(2).check(3);
+
+        // Assert
+        assert_eq!(expected, result);
+        assert_eq!(Ok(()), ExpectedVersion::Exact(3).check(3));
+    }
+
+    #[test]
+    fn conflict_is_reported_with_both_versions() {
+        let error: EventStoreError<std::convert::Infallible> = VersionConflict {
+            expected: 1,
+            actual: 2,
+        }
+        .into();
+
+        assert_eq!(
+            "stream moved on, expected version 1 but found 2",
+            error.to_string()
+        );
+    }
+}
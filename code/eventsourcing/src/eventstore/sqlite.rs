@@ -0,0 +1,565 @@
+use super::{
+    AppendResult, EventLog, EventStore, EventStoreError, ExpectedVersion, LoadResult, LogResult,
+    Position, Version, VersionConflict,
+};
+use crate::envelope::{Metadata, RecordedEvent};
+use crate::serialization::{
+    EventPayload, EventRegistry, Format, SerializationError, SerializedEvent,
+};
+use crate::{Aggregate, AggregateEvent};
+use chrono::prelude::*;
+use rusqlite::{
+    params, Connection, ErrorCode, OptionalExtension, ToSql, Transaction, TransactionBehavior,
+};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{error, fmt, thread};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        position INTEGER PRIMARY KEY AUTOINCREMENT,
+        aggregate_type TEXT NOT NULL,
+        aggregate_id TEXT NOT NULL,
+        sequence INTEGER NOT NULL,
+        event_type TEXT NOT NULL,
+        event_version INTEGER NOT NULL,
+        payload BLOB NOT NULL,
+        metadata TEXT NOT NULL,
+        recorded_at TEXT NOT NULL,
+        UNIQUE (aggregate_type, aggregate_id, sequence)
+    );
+";
+
+/// How often waiting for an append looks for new events, SQLite can not notify other connections.
+const APPEND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Keeps events of the aggregate `A` in the `events` table of an embedded SQLite database,
+/// payloads are encoded by the registry in the format `F`.
+///
+/// Appends run in an immediate transaction, so a second writer waits for the first one to commit
+/// and then sees the version it appended, getting a version conflict instead of a busy database.
+/// The unique `(aggregate_type, aggregate_id, sequence)` constraint backs that up.
+pub struct SqliteEventStore<A, E, F> {
+    connection: Mutex<Connection>,
+    registry: EventRegistry<E, F>,
+    _aggregate: PhantomData<A>,
+}
+
+impl<A, E, F> SqliteEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    /// Opens the database file, creating it together with the `events` table if needed.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        registry: EventRegistry<E, F>,
+    ) -> Result<SqliteEventStore<A, E, F>, SqliteStoreError> {
+        SqliteEventStore::with_connection(Connection::open(path)?, registry)
+    }
+
+    pub fn open_in_memory(
+        registry: EventRegistry<E, F>,
+    ) -> Result<SqliteEventStore<A, E, F>, SqliteStoreError> {
+        SqliteEventStore::with_connection(Connection::open_in_memory()?, registry)
+    }
+
+    fn with_connection(
+        connection: Connection,
+        registry: EventRegistry<E, F>,
+    ) -> Result<SqliteEventStore<A, E, F>, SqliteStoreError> {
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(SqliteEventStore {
+            connection: Mutex::new(connection),
+            registry,
+            _aggregate: PhantomData,
+        })
+    }
+}
+
+fn stream_version(
+    tx: &Transaction,
+    aggregate_type: &str,
+    aggregate_id: &str,
+) -> rusqlite::Result<Version> {
+    let version: Option<i64> = tx
+        .query_row(
+            "SELECT MAX(sequence) FROM events WHERE aggregate_type = ?1 AND aggregate_id = ?2",
+            params![aggregate_type, aggregate_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    Ok(version.unwrap_or(0) as Version)
+}
+
+fn is_unique_violation(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(err, _) => err.code == ErrorCode::ConstraintViolation,
+        _ => false,
+    }
+}
+
+impl<A, E, F> SqliteEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    /// Polls the log until its last position moves or the timeout passes.
+    fn wait_for_log_append(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let seen = self.last_position();
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            thread::sleep(APPEND_POLL_INTERVAL.min(deadline - now));
+            if self.last_position() != seen {
+                return;
+            }
+        }
+    }
+
+    fn last_position(&self) -> Option<i64> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT MAX(position) FROM events", [], |row| row.get(0))
+            .unwrap_or(None)
+    }
+
+    /// Selects events of `A` matching the condition, `?1` is bound to the aggregate type.
+    fn select(&self, condition: &str, params: &[&dyn ToSql]) -> LoadResult<E, SqliteStoreError> {
+        self.select_recorded(&format!("aggregate_type = ?1 AND {}", condition), params)?
+            .into_iter()
+            .map(|recorded| {
+                recorded
+                    .decode::<A, E, F>(&self.registry)
+                    .map_err(|err| EventStoreError::Store(err.into()))
+            })
+            .collect()
+    }
+
+    /// Selects events of any aggregate type matching the condition, without decoding them.
+    fn select_recorded(
+        &self,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> LogResult<SqliteStoreError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!(
+                "SELECT aggregate_type, aggregate_id, sequence, position, event_type,
+                        event_version, payload, metadata, recorded_at
+                 FROM events
+                 WHERE {}",
+                condition
+            ))
+            .map_err(|err| EventStoreError::Store(err.into()))?;
+
+        let rows = statement
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    SerializedEvent {
+                        event_type: row.get(4)?,
+                        event_version: row.get(5)?,
+                        payload: row.get(6)?,
+                    },
+                    row.get::<_, String>(7)?,
+                    row.get::<_, String>(8)?,
+                ))
+            })
+            .map_err(|err| EventStoreError::Store(err.into()))?;
+
+        let mut recorded = Vec::new();
+        for row in rows {
+            let (aggregate_type, aggregate_id, sequence, position, event, metadata, recorded_at) =
+                row.map_err(|err| EventStoreError::Store(err.into()))?;
+
+            recorded.push(RecordedEvent {
+                aggregate_type,
+                aggregate_id,
+                sequence: sequence as Version,
+                position: position as Position,
+                recorded_at: parse_recorded_at(&recorded_at).map_err(EventStoreError::Store)?,
+                metadata: serde_json::from_str(&metadata)
+                    .map_err(|err| EventStoreError::Store(SqliteStoreError::malformed(err)))?,
+                event,
+            });
+        }
+
+        Ok(recorded)
+    }
+}
+
+/// Every aggregate type whose store opened the same database file shares its log.
+impl<A, E, F> EventLog for SqliteEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    type Error = SqliteStoreError;
+
+    fn read_log(&self, after: Position, limit: usize) -> LogResult<Self::Error> {
+        self.select_recorded(
+            "position > ?1 ORDER BY position LIMIT ?2",
+            &[&(after as i64), &(limit as i64)],
+        )
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        self.wait_for_log_append(timeout)
+    }
+}
+
+impl<A, E, F> EventStore<A> for SqliteEventStore<A, E, F>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + EventPayload,
+    F: Format,
+{
+    type Event = E;
+    type Error = SqliteStoreError;
+
+    fn load(&self, aggregate_id: &A::Id) -> LoadResult<E, Self::Error> {
+        self.load_from(aggregate_id, 0)
+    }
+
+    fn load_from(&self, aggregate_id: &A::Id, after: Version) -> LoadResult<E, Self::Error> {
+        self.select(
+            "aggregate_id = ?2 AND sequence > ?3 ORDER BY sequence",
+            &[
+                &A::aggregate_type(),
+                &aggregate_id.to_string(),
+                &(after as i64),
+            ],
+        )
+    }
+
+    fn read_all(&self, after: Position, limit: usize) -> LoadResult<E, Self::Error> {
+        self.select(
+            "position > ?2 ORDER BY position LIMIT ?3",
+            &[&A::aggregate_type(), &(after as i64), &(limit as i64)],
+        )
+    }
+
+    fn append(
+        &self,
+        aggregate_id: &A::Id,
+        expected_version: ExpectedVersion,
+        events: &[E],
+        metadata: &Metadata,
+    ) -> AppendResult<Self::Error> {
+        let aggregate_id = aggregate_id.to_string();
+        let metadata = serde_json::to_string(metadata)
+            .map_err(|err| EventStoreError::Store(SqliteStoreError::malformed(err)))?;
+        let store_err = |err: rusqlite::Error| EventStoreError::Store(err.into());
+
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(store_err)?;
+        let version = stream_version(&tx, A::aggregate_type(), &aggregate_id).map_err(store_err)?;
+
+        expected_version.check(version)?;
+
+        for (sequence, event) in (version + 1..).zip(events) {
+            let serialized = self
+                .registry
+                .serialize(event)
+                .map_err(|err| EventStoreError::Store(err.into()))?;
+
+            let inserted = tx.execute(
+                "INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type,
+                                     event_version, payload, metadata, recorded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    A::aggregate_type(),
+                    aggregate_id,
+                    sequence as i64,
+                    serialized.event_type,
+                    serialized.event_version,
+                    serialized.payload,
+                    metadata,
+                    Utc::now().to_rfc3339(),
+                ],
+            );
+
+            match inserted {
+                Err(ref err) if is_unique_violation(err) => {
+                    let actual = stream_version(&tx, A::aggregate_type(), &aggregate_id)
+                        .map_err(store_err)?;
+                    return Err(EventStoreError::Conflict(VersionConflict {
+                        expected: version,
+                        actual,
+                    }));
+                }
+                inserted => {
+                    inserted.map_err(store_err)?;
+                }
+            }
+        }
+
+        tx.commit().map_err(store_err)?;
+
+        Ok(version + events.len() as Version)
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        self.wait_for_log_append(timeout)
+    }
+}
+
+fn parse_recorded_at(value: &str) -> Result<DateTime<Utc>, SqliteStoreError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|recorded_at| recorded_at.with_timezone(&Utc))
+        .map_err(SqliteStoreError::malformed)
+}
+
+#[derive(Debug)]
+pub enum SqliteStoreError {
+    Sqlite(rusqlite::Error),
+    Serialization(SerializationError),
+}
+
+impl SqliteStoreError {
+    fn malformed<E: fmt::Display>(err: E) -> SqliteStoreError {
+        SqliteStoreError::Serialization(SerializationError::Malformed(err.to_string()))
+    }
+}
+
+impl From<rusqlite::Error> for SqliteStoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        SqliteStoreError::Sqlite(err)
+    }
+}
+
+impl From<SerializationError> for SqliteStoreError {
+    fn from(err: SerializationError) -> Self {
+        SqliteStoreError::Serialization(err)
+    }
+}
+
+impl fmt::Display for SqliteStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SqliteStoreError::Sqlite(err) => write!(f, "sqlite failed: {}", err),
+            SqliteStoreError::Serialization(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for SqliteStoreError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::Metadata;
+    use crate::eventstore::{
+        EventStore, EventStoreError, ExpectedVersion, SqliteEventStore, VersionConflict,
+    };
+    use crate::serialization::{Binary, EventRegistry, Format};
+    use crate::test_support::{events_of, Counter, CounterEvent, Reset};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use tempfile::TempDir;
+
+    type TestEventStore<F> = SqliteEventStore<Counter, CounterEvent, F>;
+
+    fn registry<F: Format>() -> EventRegistry<CounterEvent, F> {
+        EventRegistry::new()
+            .register("added", 1, CounterEvent::Added)
+            .register("subtracted", 1, CounterEvent::Subtracted)
+            .register("reset", 2, CounterEvent::Reset)
+    }
+
+    #[test]
+    fn events_are_loaded_after_reopening_the_database() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("events.sqlite");
+        let metadata = Metadata::new().with_user_id("miro");
+        let events = vec![
+            CounterEvent::Added(5),
+            CounterEvent::Reset(Reset {
+                reason: "audit".into(),
+            }),
+        ];
+        TestEventStore::<Binary>::open(&path, registry())
+            .unwrap()
+            .append(&100, ExpectedVersion::Exact(0), &events, &metadata)
+            .unwrap();
+
+        // Act
+        let result = TestEventStore::<Binary>::open(&path, registry())
+            .unwrap()
+            .load(&100)
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![1, 2],
+            result.iter().map(|e| e.sequence).collect::<Vec<_>>()
+        );
+        assert_eq!("Counter", result[0].aggregate_type);
+        assert_eq!(metadata, result[1].metadata);
+        assert_eq!(events, events_of(result));
+    }
+
+    #[test]
+    fn streams_are_kept_apart() {
+        // Arrange
+        let event_store = TestEventStore::<Binary>::open_in_memory(registry()).unwrap();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+
+        // Act
+        let result = event_store.load(&100).unwrap();
+
+        // Assert
+        assert_eq!(vec![CounterEvent::Added(1)], events_of(result));
+        assert!(event_store.load(&102).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_all_returns_a_page_of_events_after_position() {
+        // Arrange
+        let event_store = TestEventStore::<Binary>::open_in_memory(registry()).unwrap();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        append(&event_store, 100, &[CounterEvent::Added(3)]);
+
+        // Act
+        let result = event_store.read_all(1, 1).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![2],
+            result.iter().map(|e| e.position).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![CounterEvent::Added(2)], events_of(result));
+        assert_eq!(
+            vec![CounterEvent::Added(3)],
+            events_of(event_store.read_all(2, 10).unwrap())
+        );
+    }
+
+    #[test]
+    fn append_on_stale_version_is_rejected() {
+        // Arrange
+        let event_store = TestEventStore::<Binary>::open_in_memory(registry()).unwrap();
+        append(&event_store, 100, &[CounterEvent::Added(1)]);
+        let expected = VersionConflict {
+            expected: 0,
+            actual: 1,
+        };
+
+        // Act
+        let result = event_store.append(
+            &100,
+            ExpectedVersion::Exact(0),
+            &[CounterEvent::Added(2)],
+            &Metadata::new(),
+        );
+
+        // Assert
+        match result {
+            Err(EventStoreError::Conflict(conflict)) => assert_eq!(expected, conflict),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn second_writer_of_the_same_version_gets_a_conflict() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("events.sqlite");
+        let first = TestEventStore::<Binary>::open(&path, registry()).unwrap();
+        let second = TestEventStore::<Binary>::open(&path, registry()).unwrap();
+        append(&first, 100, &[CounterEvent::Added(1)]);
+
+        // Act
+        let result = second.append(
+            &100,
+            ExpectedVersion::Exact(0),
+            &[CounterEvent::Added(2)],
+            &Metadata::new(),
+        );
+
+        // Assert
+        match result {
+            Err(EventStoreError::Conflict(_)) => (),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert_eq!(
+            vec![CounterEvent::Added(1)],
+            events_of(first.load(&100).unwrap())
+        );
+    }
+
+    #[test]
+    fn racing_writers_on_separate_connections_get_a_conflict() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("events.sqlite");
+        let barrier = Arc::new(Barrier::new(2));
+        let writers: Vec<_> = (1..=2)
+            .map(|amount| {
+                let event_store = TestEventStore::<Binary>::open(&path, registry()).unwrap();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    (0..20)
+                        .map(|id| {
+                            barrier.wait();
+                            event_store.append(
+                                &id,
+                                ExpectedVersion::Exact(0),
+                                &[CounterEvent::Added(amount)],
+                                &Metadata::new(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        // Act
+        let results: Vec<_> = writers
+            .into_iter()
+            .map(|writer| writer.join().unwrap())
+            .collect();
+
+        // Assert
+        for (first, second) in results[0].iter().zip(&results[1]) {
+            match (first, second) {
+                (Ok(1), Err(EventStoreError::Conflict(conflict)))
+                | (Err(EventStoreError::Conflict(conflict)), Ok(1)) => assert_eq!(
+                    VersionConflict {
+                        expected: 0,
+                        actual: 1
+                    },
+                    *conflict
+                ),
+                other => panic!("expected one append and one conflict, got {:?}", other),
+            }
+        }
+    }
+
+    fn append<F: Format>(event_store: &TestEventStore<F>, id: u64, events: &[CounterEvent]) {
+        event_store
+            .append(&id, ExpectedVersion::Any, events, &Metadata::new())
+            .unwrap();
+    }
+}
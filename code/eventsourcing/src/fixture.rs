@@ -0,0 +1,236 @@
+//! Given/When/Then harness for testing aggregates without any store: given the events the
+//! aggregate went through, when it executes the command, then it produces the expected events or
+//! fails with the expected error.
+
+use crate::{Aggregate, AggregateCommand, AggregateEvent, ProducedEvent};
+use std::fmt::{Debug, Write};
+
+pub struct AggregateFixture<A> {
+    aggregate: A,
+}
+
+impl<A: Aggregate> AggregateFixture<A> {
+    /// Starts from the aggregate the events were applied to, in the order given.
+    #[track_caller]
+    pub fn given<E, I>(events: I) -> AggregateFixture<A>
+    where
+        E: AggregateEvent<A> + Debug,
+        I: IntoIterator<Item = E>,
+    {
+        let mut aggregate = A::default();
+        for (index, event) in events.into_iter().enumerate() {
+            let description = format!("{:?}", event);
+            if let Err(err) = aggregate.apply(event) {
+                panic!(
+                    "can not apply given event #{} {}: {}",
+                    index, description, err
+                );
+            }
+        }
+
+        AggregateFixture { aggregate }
+    }
+
+    /// Starts from the aggregate nothing happened to yet.
+    pub fn given_no_events() -> AggregateFixture<A> {
+        AggregateFixture {
+            aggregate: A::default(),
+        }
+    }
+
+    pub fn when<C>(self, command: C) -> FixtureResult<A, C>
+    where
+        C: AggregateCommand<A>,
+    {
+        let result = self.aggregate.execute(command);
+
+        FixtureResult {
+            aggregate: self.aggregate,
+            result,
+        }
+    }
+}
+
+/// Outcome of the command, with the aggregate as it was before executing it.
+pub struct FixtureResult<A, C>
+where
+    A: Aggregate,
+    C: AggregateCommand<A>,
+{
+    aggregate: A,
+    result: Result<C::Events, C::Error>,
+}
+
+impl<A, C> FixtureResult<A, C>
+where
+    A: Aggregate,
+    C: AggregateCommand<A>,
+    ProducedEvent<A, C>: PartialEq + Debug,
+{
+    /// Checks the command produced exactly the expected events and applies them, so the state
+    /// they lead to can be checked next.
+    #[track_caller]
+    pub fn then_expect_events(self, expected: Vec<ProducedEvent<A, C>>) -> FixtureState<A> {
+        let events = match self.result {
+            Ok(events) => events,
+            Err(err) => panic!(
+                "expected the command to produce events, it failed with {:?}\nexpected events:\n{}",
+                err,
+                list(&expected)
+            ),
+        };
+
+        if expected.as_slice() != events.as_ref() {
+            panic!(
+                "produced events differ from the expected ones (-expected +produced):\n{}",
+                diff(&expected, events.as_ref())
+            );
+        }
+
+        let mut aggregate = self.aggregate;
+        for (index, event) in events.into_iter().enumerate() {
+            let description = format!("{:?}", event);
+            if let Err(err) = aggregate.apply(event) {
+                panic!(
+                    "can not apply produced event #{} {}: {}",
+                    index, description, err
+                );
+            }
+        }
+
+        FixtureState { aggregate }
+    }
+
+    #[track_caller]
+    pub fn then_expect_error(self, expected: C::Error)
+    where
+        C::Error: PartialEq,
+    {
+        match self.result {
+            Err(ref err) if *err == expected => {}
+            Err(err) => panic!(
+                "command failed with a different error\nexpected: {:?}\n  actual: {:?}",
+                expected, err
+            ),
+            Ok(events) => panic!(
+                "expected the command to fail with {:?}, it produced events:\n{}",
+                expected,
+                list(events.as_ref())
+            ),
+        }
+    }
+}
+
+/// Aggregate after the produced events got applied.
+pub struct FixtureState<A> {
+    aggregate: A,
+}
+
+impl<A: Aggregate> FixtureState<A> {
+    #[track_caller]
+    pub fn then_expect_state(self, expected: A)
+    where
+        A: PartialEq + Debug,
+    {
+        if self.aggregate != expected {
+            panic!(
+                "state after the produced events differs\nexpected: {:#?}\n  actual: {:#?}",
+                expected, self.aggregate
+            );
+        }
+    }
+
+    pub fn then_inspect_state<F>(self, inspect: F)
+    where
+        F: FnOnce(&A),
+    {
+        inspect(&self.aggregate);
+    }
+
+    pub fn into_aggregate(self) -> A {
+        self.aggregate
+    }
+}
+
+fn list<T: Debug>(items: &[T]) -> String {
+    if items.is_empty() {
+        return "  (none)\n".to_owned();
+    }
+
+    let mut out = String::new();
+    for (index, item) in items.iter().enumerate() {
+        let _ = writeln!(out, "  #{} {:?}", index, item);
+    }
+    out
+}
+
+/// One line per position, the ones that differ twice: the expected item marked with `-` and the
+/// produced one with `+`.
+fn diff<T: Debug + PartialEq>(expected: &[T], produced: &[T]) -> String {
+    let mut out = String::new();
+    for index in 0..expected.len().max(produced.len()) {
+        match (expected.get(index), produced.get(index)) {
+            (Some(expected), Some(produced)) if expected == produced => {
+                let _ = writeln!(out, "  #{} {:?}", index, expected);
+            }
+            (expected, produced) => {
+                if let Some(expected) = expected {
+                    let _ = writeln!(out, "- #{} {:?}", index, expected);
+                }
+                if let Some(produced) = produced {
+                    let _ = writeln!(out, "+ #{} {:?}", index, produced);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixture::{diff, AggregateFixture};
+    use crate::test_support::{Add, Counter, CounterError, CounterEvent, Subtract};
+
+    #[test]
+    fn produced_events_lead_to_the_expected_state() {
+        AggregateFixture::<Counter>::given(vec![CounterEvent::Added(5)])
+            .when(Subtract(2))
+            .then_expect_events(vec![CounterEvent::Subtracted(2)])
+            .then_expect_state(Counter {
+                value: 3,
+                generation: 2,
+            });
+    }
+
+    #[test]
+    fn rejected_command_fails_with_the_expected_error() {
+        AggregateFixture::<Counter>::given_no_events()
+            .when(Subtract(2))
+            .then_expect_error(CounterError::BelowZero);
+    }
+
+    #[test]
+    #[should_panic(expected = "- #0 Added(2)\n+ #0 Added(3)\n")]
+    fn different_events_fail_with_a_diff() {
+        AggregateFixture::<Counter>::given_no_events()
+            .when(Add(3))
+            .then_expect_events(vec![CounterEvent::Added(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected the command to fail with BelowZero, it produced events:")]
+    fn unexpected_success_fails() {
+        AggregateFixture::<Counter>::given_no_events()
+            .when(Add(3))
+            .then_expect_error(CounterError::BelowZero);
+    }
+
+    #[test]
+    fn diff_marks_missing_and_extra_events() {
+        // Act
+        let result = diff(&[1, 2], &[1, 3, 4]);
+
+        // Assert
+        assert_eq!("  #0 1\n- #1 2\n+ #1 3\n+ #2 4\n", result);
+    }
+}
@@ -1,9 +1,32 @@
+pub mod envelope;
 pub mod eventstore;
+pub mod fixture;
+pub mod projection;
+pub mod publisher;
+pub mod repository;
+pub mod serialization;
+pub mod snapshot;
+pub mod subscription;
+#[cfg(test)]
+mod test_support;
+pub mod tracked;
+pub mod upcasting;
+pub mod versioned;
 
+use crate::eventstore::Version;
 use std::fmt;
 
 pub trait Aggregate: Default {
+    type Id: AggregateId<Self>;
+
     fn aggregate_type() -> &'static str;
+
+    /// Id of the stream the aggregate belongs to, `None` until an event told it.
+    fn id(&self) -> Option<&Self::Id>;
+
+    /// Number of events applied so far, that is the version of the stream it got rehydrated from.
+    fn version(&self) -> Version;
+
     fn increment_generation(&mut self);
 
     fn execute<C>(&self, command: C) -> Result<C::Events, C::Error>
@@ -23,8 +46,35 @@ pub trait Aggregate: Default {
     }
 }
 
+/// Number of events applied to an aggregate, the `Aggregate` derive keeps it up to date in the
+/// field marked `#[aggregate(generation)]` and `Versioned` next to the state of enum aggregates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Generation(u64);
+
+impl Generation {
+    pub fn new(value: u64) -> Generation {
+        Generation(value)
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+
+    pub fn increment(&mut self) {
+        self.0 += 1;
+    }
+}
+
+/// Identifies a single stream of events of the aggregate `A`.
+pub trait AggregateId<A: Aggregate>: fmt::Display {}
+
 pub trait Event {
     fn event_type(&self) -> &'static str;
+
+    /// Schema version of the event payload, bumped whenever its shape changes.
+    fn event_version(&self) -> u32 {
+        1
+    }
 }
 
 pub trait AggregateEvent<A: Aggregate>: Event {
@@ -60,8 +110,24 @@ impl<T> CqrsError for T where T: fmt::Debug + fmt::Display + Send + Sync + 'stat
 
 #[cfg(test)]
 mod tests {
+    use crate::Generation;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn generation_counts_up_from_zero() {
+        // Arrange
+        let mut generation = Generation::default();
+
+        // Act
+        generation.increment();
+        generation.increment();
+
+        // Assert
+        assert_eq!(Generation::new(2), generation);
+        assert_eq!(2, generation.value());
+    }
 }
@@ -0,0 +1,488 @@
+use crate::envelope::EventEnvelope;
+use crate::eventstore::{EventStore, EventStoreError, Position};
+use crate::{Aggregate, AggregateEvent, CqrsError};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::{error, fmt};
+
+pub trait CheckpointStore {
+    type Error: CqrsError;
+
+    /// Returns the position of the last event the projection handled, 0 if it handled none.
+    fn load_checkpoint(&self, projection: &str) -> Result<Position, Self::Error>;
+    fn save_checkpoint(&self, projection: &str, position: Position) -> Result<(), Self::Error>;
+}
+
+/// Lets a runner save checkpoints somebody else can look at.
+impl<C: CheckpointStore> CheckpointStore for Arc<C> {
+    type Error = C::Error;
+
+    fn load_checkpoint(&self, projection: &str) -> Result<Position, Self::Error> {
+        (**self).load_checkpoint(projection)
+    }
+
+    fn save_checkpoint(&self, projection: &str, position: Position) -> Result<(), Self::Error> {
+        (**self).save_checkpoint(projection, position)
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryCheckpointStore {
+    checkpoints: RwLock<HashMap<String, Position>>,
+}
+
+impl InMemoryCheckpointStore {
+    pub fn new() -> InMemoryCheckpointStore {
+        InMemoryCheckpointStore {
+            checkpoints: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl CheckpointStore for InMemoryCheckpointStore {
+    type Error = Infallible;
+
+    fn load_checkpoint(&self, projection: &str) -> Result<Position, Self::Error> {
+        let checkpoints = self.checkpoints.read().unwrap();
+
+        Ok(checkpoints.get(projection).cloned().unwrap_or(0))
+    }
+
+    fn save_checkpoint(&self, projection: &str, position: Position) -> Result<(), Self::Error> {
+        let mut checkpoints = self.checkpoints.write().unwrap();
+        checkpoints.insert(projection.to_owned(), position);
+
+        Ok(())
+    }
+}
+
+/// Read model kept up to date with committed events of the aggregate `A`.
+///
+/// The checkpoint is saved after the events got handled, so after a crash an event can be
+/// handled twice and handlers should be idempotent.
+pub trait Projection<A: Aggregate> {
+    type Event: AggregateEvent<A>;
+    type Error: CqrsError;
+
+    /// Name the checkpoint of the projection is stored under.
+    fn name(&self) -> &str;
+    fn handle(&mut self, envelope: &EventEnvelope<Self::Event>) -> Result<(), Self::Error>;
+}
+
+/// Projection which can be re-derived from the whole history next to the live one, e.g. after
+/// the way it handles events changed.
+pub trait Rebuild<A: Aggregate>: Projection<A> + Sized {
+    /// Target to replay the history into, without touching what the live projection serves.
+    fn fresh(&self) -> Self;
+
+    /// Throws away whatever the target still holds from an earlier, interrupted rebuild.
+    fn truncate(&mut self) -> Result<(), Self::Error>;
+
+    /// Replaces everything the live projection serves with the rebuilt one in a single step.
+    fn swap(&mut self, rebuilt: Self) -> Result<(), Self::Error>;
+}
+
+/// Reported after every batch of a rebuild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebuildProgress {
+    pub handled: usize,
+    /// Position of the last replayed event.
+    pub position: Position,
+}
+
+pub type ProjectionErrorOf<A, S, P, C> = ProjectionError<
+    <P as Projection<A>>::Error,
+    <S as EventStore<A>>::Error,
+    <C as CheckpointStore>::Error,
+>;
+
+/// Number of events a runner reads from the store at once while catching up.
+const BATCH_SIZE: usize = 100;
+
+/// Feeds events from the store into a projection, starting where its checkpoint left off.
+pub struct ProjectionRunner<A, S, P, C> {
+    event_store: S,
+    projection: P,
+    checkpoints: C,
+    _aggregate: PhantomData<A>,
+}
+
+impl<A, S, P, C> ProjectionRunner<A, S, P, C>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+    P: Projection<A, Event = S::Event>,
+    C: CheckpointStore,
+{
+    pub fn new(event_store: S, projection: P, checkpoints: C) -> ProjectionRunner<A, S, P, C> {
+        ProjectionRunner {
+            event_store,
+            projection,
+            checkpoints,
+            _aggregate: PhantomData,
+        }
+    }
+
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Handles all events appended since the last run and returns how many there were. The
+    /// checkpoint is saved after every batch of events.
+    pub fn catch_up(&mut self) -> Result<usize, ProjectionErrorOf<A, S, P, C>> {
+        let mut position = self
+            .checkpoints
+            .load_checkpoint(self.projection.name())
+            .map_err(ProjectionError::Checkpoint)?;
+
+        let mut handled = 0;
+        loop {
+            let envelopes = self
+                .event_store
+                .read_all(position, BATCH_SIZE)
+                .map_err(ProjectionError::Store)?;
+            if envelopes.is_empty() {
+                return Ok(handled);
+            }
+
+            for envelope in &envelopes {
+                self.projection
+                    .handle(envelope)
+                    .map_err(ProjectionError::Projection)?;
+                position = envelope.position;
+            }
+            handled += envelopes.len();
+
+            self.checkpoints
+                .save_checkpoint(self.projection.name(), position)
+                .map_err(ProjectionError::Checkpoint)?;
+        }
+    }
+
+    /// Resets the checkpoint, truncates a fresh target and replays the whole history into it in
+    /// batches of `batch_size` events, saving the checkpoint after every batch. The target gets
+    /// swapped in once done, until then the live projection keeps serving. The checkpoint does
+    /// not match the live projection during a rebuild, so an interrupted one has to be started
+    /// over before catching up again.
+    pub fn rebuild<R>(
+        &mut self,
+        batch_size: usize,
+        mut progress: R,
+    ) -> Result<usize, ProjectionErrorOf<A, S, P, C>>
+    where
+        P: Rebuild<A>,
+        R: FnMut(RebuildProgress),
+    {
+        let mut position = 0;
+        self.checkpoints
+            .save_checkpoint(self.projection.name(), position)
+            .map_err(ProjectionError::Checkpoint)?;
+        let mut rebuilt = self.projection.fresh();
+        rebuilt.truncate().map_err(ProjectionError::Projection)?;
+
+        let mut handled = 0;
+        loop {
+            let envelopes = self
+                .event_store
+                .read_all(position, batch_size.max(1))
+                .map_err(ProjectionError::Store)?;
+            if envelopes.is_empty() {
+                break;
+            }
+
+            for envelope in &envelopes {
+                rebuilt
+                    .handle(envelope)
+                    .map_err(ProjectionError::Projection)?;
+                position = envelope.position;
+            }
+            handled += envelopes.len();
+
+            self.checkpoints
+                .save_checkpoint(self.projection.name(), position)
+                .map_err(ProjectionError::Checkpoint)?;
+            progress(RebuildProgress { handled, position });
+        }
+
+        self.projection
+            .swap(rebuilt)
+            .map_err(ProjectionError::Projection)?;
+
+        Ok(handled)
+    }
+
+    /// Catches up and keeps polling for new events until `running` gets switched off.
+    pub fn follow(
+        &mut self,
+        poll_interval: Duration,
+        running: &AtomicBool,
+    ) -> Result<(), ProjectionErrorOf<A, S, P, C>> {
+        while running.load(Ordering::SeqCst) {
+            if self.catch_up()? == 0 {
+                self.event_store.wait_for_append(poll_interval);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectionError<P, S, C> {
+    Projection(P),
+    Store(EventStoreError<S>),
+    Checkpoint(C),
+}
+
+impl<P, S, C> fmt::Display for ProjectionError<P, S, C>
+where
+    P: fmt::Display,
+    S: fmt::Display,
+    C: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectionError::Projection(err) => write!(f, "projection failed: {}", err),
+            ProjectionError::Store(err) => err.fmt(f),
+            ProjectionError::Checkpoint(err) => write!(f, "can not store checkpoint: {}", err),
+        }
+    }
+}
+
+impl<P, S, C> error::Error for ProjectionError<P, S, C>
+where
+    P: fmt::Debug + fmt::Display,
+    S: fmt::Debug + fmt::Display,
+    C: fmt::Debug + fmt::Display,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::{EventEnvelope, Metadata};
+    use crate::eventstore::{EventStore, ExpectedVersion, InMemoryEventStore};
+    use crate::projection::{
+        CheckpointStore, InMemoryCheckpointStore, Projection, ProjectionRunner, Rebuild,
+        RebuildProgress,
+    };
+    use crate::test_support::{Counter, CounterEvent};
+    use std::collections::BTreeMap;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Sum of everything ever added per counter.
+    #[derive(Default)]
+    struct TotalAdded {
+        totals: BTreeMap<String, u64>,
+    }
+
+    impl Projection<Counter> for TotalAdded {
+        type Event = CounterEvent;
+        type Error = Infallible;
+
+        fn name(&self) -> &str {
+            "total_added"
+        }
+
+        fn handle(&mut self, envelope: &EventEnvelope<CounterEvent>) -> Result<(), Infallible> {
+            if let CounterEvent::Added(amount) = envelope.event {
+                *self
+                    .totals
+                    .entry(envelope.aggregate_id.clone())
+                    .or_default() += amount;
+            }
+            Ok(())
+        }
+    }
+
+    impl Rebuild<Counter> for TotalAdded {
+        fn fresh(&self) -> Self {
+            TotalAdded::default()
+        }
+
+        fn truncate(&mut self) -> Result<(), Infallible> {
+            self.totals.clear();
+            Ok(())
+        }
+
+        fn swap(&mut self, rebuilt: Self) -> Result<(), Infallible> {
+            *self = rebuilt;
+            Ok(())
+        }
+    }
+
+    fn append(
+        event_store: &InMemoryEventStore<Counter, CounterEvent>,
+        id: u64,
+        events: &[CounterEvent],
+    ) {
+        event_store
+            .append(&id, ExpectedVersion::Any, events, &Metadata::new())
+            .unwrap();
+    }
+
+    #[test]
+    fn catch_up_handles_events_of_all_streams() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(5), CounterEvent::Subtracted(1)],
+        );
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        let mut runner = ProjectionRunner::new(
+            Arc::clone(&event_store),
+            TotalAdded::default(),
+            InMemoryCheckpointStore::new(),
+        );
+
+        // Act
+        let result = runner.catch_up();
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert_eq!(Some(&5), runner.projection().totals.get("100"));
+        assert_eq!(Some(&2), runner.projection().totals.get("101"));
+    }
+
+    #[test]
+    fn catch_up_continues_from_the_checkpoint() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let checkpoints = Arc::new(InMemoryCheckpointStore::new());
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        ProjectionRunner::new(
+            Arc::clone(&event_store),
+            TotalAdded::default(),
+            Arc::clone(&checkpoints),
+        )
+        .catch_up()
+        .unwrap();
+        append(&event_store, 100, &[CounterEvent::Added(3)]);
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        let mut restarted = ProjectionRunner::new(
+            Arc::clone(&event_store),
+            TotalAdded::default(),
+            Arc::clone(&checkpoints),
+        );
+
+        // Act
+        let result = restarted.catch_up();
+
+        // Assert
+        assert_eq!(Ok(2), result);
+        assert_eq!(Some(&3), restarted.projection().totals.get("100"));
+        assert_eq!(Ok(3), checkpoints.load_checkpoint("total_added"));
+        assert_eq!(Ok(0), restarted.catch_up());
+    }
+
+    #[test]
+    fn follow_picks_up_live_appends() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let running = Arc::new(AtomicBool::new(true));
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let follower = {
+            let event_store = Arc::clone(&event_store);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                let mut runner = ProjectionRunner::new(
+                    event_store,
+                    TotalAdded::default(),
+                    InMemoryCheckpointStore::new(),
+                );
+                runner.follow(Duration::from_millis(1), &running).unwrap();
+                runner.projection().totals.clone()
+            })
+        };
+
+        // Act
+        append(&event_store, 100, &[CounterEvent::Added(3)]);
+        thread::sleep(Duration::from_millis(100));
+        running.store(false, Ordering::SeqCst);
+
+        // Assert
+        let totals = follower.join().unwrap();
+        assert_eq!(Some(&8), totals.get("100"));
+    }
+
+    #[test]
+    fn rebuild_replays_history_into_fresh_projection() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let checkpoints = Arc::new(InMemoryCheckpointStore::new());
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(5), CounterEvent::Added(1)],
+        );
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        let mut stale = TotalAdded::default();
+        stale.totals.insert("100".into(), 1000);
+        let mut runner =
+            ProjectionRunner::new(Arc::clone(&event_store), stale, Arc::clone(&checkpoints));
+        let mut reported = Vec::new();
+
+        // Act
+        let result = runner.rebuild(2, |progress| reported.push(progress));
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert_eq!(Some(&6), runner.projection().totals.get("100"));
+        assert_eq!(Some(&2), runner.projection().totals.get("101"));
+        assert_eq!(
+            vec![
+                RebuildProgress {
+                    handled: 2,
+                    position: 2
+                },
+                RebuildProgress {
+                    handled: 3,
+                    position: 3
+                },
+            ],
+            reported
+        );
+        assert_eq!(Ok(0), runner.catch_up());
+    }
+
+    #[test]
+    fn rebuild_after_partial_catch_up_starts_from_the_first_position() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let checkpoints = Arc::new(InMemoryCheckpointStore::new());
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(5), CounterEvent::Added(1)],
+        );
+        let mut runner = ProjectionRunner::new(
+            Arc::clone(&event_store),
+            TotalAdded::default(),
+            Arc::clone(&checkpoints),
+        );
+        runner.catch_up().unwrap();
+        append(&event_store, 101, &[CounterEvent::Added(2)]);
+        let mut reported = Vec::new();
+
+        // Act
+        let result = runner.rebuild(1, |progress| {
+            let checkpoint = checkpoints.load_checkpoint("total_added").unwrap();
+            reported.push((progress.position, checkpoint));
+        });
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert_eq!(vec![(1, 1), (2, 2), (3, 3)], reported);
+        assert_eq!(Some(&6), runner.projection().totals.get("100"));
+        assert_eq!(Some(&2), runner.projection().totals.get("101"));
+    }
+}
@@ -0,0 +1,47 @@
+use crate::envelope::EventEnvelope;
+use crate::Event;
+use std::sync::Arc;
+
+/// Gets told about events right after the repository appended them, e.g. to hand them over to
+/// in-process listeners. Their positions are not known at that point and left at 0, follow the
+/// store with a subscription where they matter.
+pub trait EventPublisher<E: Event> {
+    fn publish(&self, envelopes: &[EventEnvelope<E>]);
+}
+
+impl<E, P> EventPublisher<E> for Arc<P>
+where
+    E: Event,
+    P: EventPublisher<E>,
+{
+    fn publish(&self, envelopes: &[EventEnvelope<E>]) {
+        (**self).publish(envelopes)
+    }
+}
+
+#[cfg(feature = "xbus")]
+mod bus {
+    use super::EventPublisher;
+    use crate::envelope::EventEnvelope;
+    use crate::Event;
+    use xbus::{EventBus, XbusEvent};
+
+    impl<E: Event + 'static> XbusEvent for EventEnvelope<E> {
+        fn event_type(&self) -> &str {
+            EventEnvelope::event_type(self)
+        }
+
+        fn aggregate_type(&self) -> &str {
+            self.aggregate_type
+        }
+    }
+
+    /// Lets listeners of the bus react to committed events, one envelope at a time.
+    impl<E: Event + 'static> EventPublisher<E> for EventBus<EventEnvelope<E>> {
+        fn publish(&self, envelopes: &[EventEnvelope<E>]) {
+            for envelope in envelopes {
+                EventBus::publish(self, envelope);
+            }
+        }
+    }
+}
@@ -0,0 +1,741 @@
+use crate::envelope::{EventEnvelope, Metadata};
+use crate::eventstore::{EventStore, EventStoreError, ExpectedVersion, Version};
+use crate::publisher::EventPublisher;
+use crate::snapshot::{NoSnapshots, Snapshot, SnapshotPolicy, SnapshotStore};
+use crate::tracked::{Tracked, TrackedError};
+use crate::{Aggregate, AggregateCommand, AggregateEvent};
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::{error, fmt};
+
+/// Error of applying an event the store of aggregate `A` returned.
+pub type EventError<A, S> = <<S as EventStore<A>>::Event as AggregateEvent<A>>::Error;
+/// Error of the store itself.
+pub type StoreError<A, S> = <S as EventStore<A>>::Error;
+/// Error of the snapshot store.
+pub type SnapshotError<A, P> = <P as SnapshotStore<A>>::Error;
+
+pub type LoadErrorOf<A, S, P = NoSnapshots> =
+    LoadError<EventError<A, S>, StoreError<A, S>, SnapshotError<A, P>>;
+pub type LoadResult<A, S, P = NoSnapshots> = Result<A, LoadErrorOf<A, S, P>>;
+pub type SaveResult<A, S> = Result<Version, EventStoreError<StoreError<A, S>>>;
+pub type ExecuteResult<A, S, C, P = NoSnapshots> = Result<
+    Version,
+    ExecuteError<
+        <C as AggregateCommand<A>>::Error,
+        EventError<A, S>,
+        StoreError<A, S>,
+        SnapshotError<A, P>,
+    >,
+>;
+
+/// Rehydrates aggregates from their event stream and appends events produced by commands, or
+/// recorded on a `Tracked` aggregate.
+///
+/// With a snapshot store the latest snapshot is the starting point and only events appended
+/// after it get replayed. Events appended by `execute` as well as `save` get published to all
+/// publishers right after the append.
+pub struct Repository<A, S, P = NoSnapshots>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    event_store: S,
+    snapshot_store: P,
+    snapshot_policy: SnapshotPolicy,
+    publishers: Vec<Box<dyn EventPublisher<S::Event> + Send + Sync>>,
+    _aggregate: PhantomData<A>,
+}
+
+impl<A, S> Repository<A, S>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    pub fn new(event_store: S) -> Repository<A, S> {
+        Repository::with_snapshots(event_store, NoSnapshots, SnapshotPolicy::Never)
+    }
+}
+
+impl<A, S, P> Repository<A, S, P>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+    P: SnapshotStore<A>,
+{
+    pub fn with_snapshots(
+        event_store: S,
+        snapshot_store: P,
+        snapshot_policy: SnapshotPolicy,
+    ) -> Repository<A, S, P> {
+        Repository {
+            event_store,
+            snapshot_store,
+            snapshot_policy,
+            publishers: Vec::new(),
+            _aggregate: PhantomData,
+        }
+    }
+
+    /// Publishes events appended through the repository to the publisher as well.
+    pub fn with_publisher<B>(mut self, publisher: B) -> Repository<A, S, P>
+    where
+        B: EventPublisher<S::Event> + Send + Sync + 'static,
+    {
+        self.publishers.push(Box::new(publisher));
+        self
+    }
+
+    pub fn event_store(&self) -> &S {
+        &self.event_store
+    }
+
+    pub fn snapshot_store(&self) -> &P {
+        &self.snapshot_store
+    }
+
+    pub fn load(&self, aggregate_id: &A::Id) -> LoadResult<A, S, P> {
+        self.rehydrate(aggregate_id)
+    }
+
+    /// Executes the command on the current state of the aggregate and appends produced events,
+    /// failing with a conflict if somebody else appended to the stream in the meantime.
+    pub fn execute<C>(&self, aggregate_id: &A::Id, command: C) -> ExecuteResult<A, S, C, P>
+    where
+        A: Clone,
+        C: AggregateCommand<A, Event = S::Event>,
+        S::Event: Clone,
+    {
+        self.execute_with_metadata(aggregate_id, command, &Metadata::new())
+    }
+
+    pub fn execute_with_metadata<C>(
+        &self,
+        aggregate_id: &A::Id,
+        command: C,
+        metadata: &Metadata,
+    ) -> ExecuteResult<A, S, C, P>
+    where
+        A: Clone,
+        C: AggregateCommand<A, Event = S::Event>,
+        S::Event: Clone,
+    {
+        let mut aggregate = Tracked::new(self.rehydrate(aggregate_id)?);
+
+        aggregate.execute(command)?;
+
+        self.save_with_metadata(aggregate_id, &mut aggregate, metadata)
+            .map_err(ExecuteError::Store)
+    }
+
+    /// Appends the events recorded on the aggregate since it got loaded, failing with a conflict
+    /// if somebody else appended to the stream in the meantime. They are not tracked any more once
+    /// appended.
+    pub fn save(
+        &self,
+        aggregate_id: &A::Id,
+        aggregate: &mut Tracked<A, S::Event>,
+    ) -> SaveResult<A, S>
+    where
+        A: Clone,
+    {
+        self.save_with_metadata(aggregate_id, aggregate, &Metadata::new())
+    }
+
+    pub fn save_with_metadata(
+        &self,
+        aggregate_id: &A::Id,
+        aggregate: &mut Tracked<A, S::Event>,
+        metadata: &Metadata,
+    ) -> SaveResult<A, S>
+    where
+        A: Clone,
+    {
+        let version = aggregate.committed_version();
+
+        let new_version = self.event_store.append(
+            aggregate_id,
+            ExpectedVersion::Exact(version),
+            aggregate.uncommitted(),
+            metadata,
+        )?;
+        let events = aggregate.take_uncommitted();
+
+        if self.snapshot_policy.should_snapshot(version, new_version) {
+            self.take_snapshot(aggregate_id, aggregate);
+        }
+        self.publish(aggregate_id, version, events, metadata);
+
+        Ok(new_version)
+    }
+
+    /// Events are already committed at this point and a missing snapshot only makes the next
+    /// load replay more of them, so failures here do not fail the command. The saved aggregate is
+    /// exactly at the version just appended, there is no need to load it again.
+    fn take_snapshot(&self, aggregate_id: &A::Id, aggregate: &A)
+    where
+        A: Clone,
+    {
+        let _ = self
+            .snapshot_store
+            .save_snapshot(aggregate_id, Snapshot::of(aggregate.clone()));
+    }
+
+    /// Wraps the events just appended instead of loading them back, so nothing can fail once
+    /// they are committed. Only the store knows their positions, those are left at 0.
+    fn publish(
+        &self,
+        aggregate_id: &A::Id,
+        previous: Version,
+        events: Vec<S::Event>,
+        metadata: &Metadata,
+    ) {
+        if self.publishers.is_empty() || events.is_empty() {
+            return;
+        }
+
+        let envelopes: Vec<_> = (previous + 1..)
+            .zip(events)
+            .map(|(sequence, event)| {
+                EventEnvelope::new::<A>(
+                    aggregate_id.to_string(),
+                    sequence,
+                    0,
+                    event,
+                    metadata.clone(),
+                )
+            })
+            .collect();
+
+        for publisher in &self.publishers {
+            publisher.publish(&envelopes);
+        }
+    }
+
+    /// Applies the events appended after the latest snapshot, the aggregate ends up at the
+    /// version of the stream. A snapshot whose aggregate is not at the version it was taken at
+    /// can not be trusted and the whole stream is replayed instead.
+    fn rehydrate(&self, aggregate_id: &A::Id) -> LoadResult<A, S, P> {
+        let (mut aggregate, version) = match self
+            .snapshot_store
+            .load_snapshot(aggregate_id)
+            .map_err(LoadError::Snapshot)?
+        {
+            Some(snapshot) if snapshot.version == snapshot.aggregate.version() => {
+                (snapshot.aggregate, snapshot.version)
+            }
+            _ => (A::default(), 0),
+        };
+
+        let envelopes = self
+            .event_store
+            .load_from(aggregate_id, version)
+            .map_err(LoadError::Store)?;
+
+        for envelope in envelopes {
+            aggregate.apply(envelope.event).map_err(LoadError::Event)?;
+        }
+
+        Ok(aggregate)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError<E, S, P = Infallible> {
+    Event(E),
+    Store(EventStoreError<S>),
+    Snapshot(P),
+}
+
+impl<E, S, P> fmt::Display for LoadError<E, S, P>
+where
+    E: fmt::Display,
+    S: fmt::Display,
+    P: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Event(err) => write!(f, "can not apply stored event: {}", err),
+            LoadError::Store(err) => err.fmt(f),
+            LoadError::Snapshot(err) => write!(f, "can not load snapshot: {}", err),
+        }
+    }
+}
+
+impl<E, S, P> error::Error for LoadError<E, S, P>
+where
+    E: fmt::Debug + fmt::Display,
+    S: fmt::Debug + fmt::Display,
+    P: fmt::Debug + fmt::Display,
+{
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecuteError<C, E, S, P = Infallible> {
+    Command(C),
+    Event(E),
+    Store(EventStoreError<S>),
+    Snapshot(P),
+}
+
+impl<C, E, S, P> From<TrackedError<C, E>> for ExecuteError<C, E, S, P> {
+    fn from(err: TrackedError<C, E>) -> Self {
+        match err {
+            TrackedError::Command(err) => ExecuteError::Command(err),
+            TrackedError::Event(err) => ExecuteError::Event(err),
+        }
+    }
+}
+
+impl<C, E, S, P> From<LoadError<E, S, P>> for ExecuteError<C, E, S, P> {
+    fn from(err: LoadError<E, S, P>) -> Self {
+        match err {
+            LoadError::Event(err) => ExecuteError::Event(err),
+            LoadError::Store(err) => ExecuteError::Store(err),
+            LoadError::Snapshot(err) => ExecuteError::Snapshot(err),
+        }
+    }
+}
+
+impl<C, E, S, P> fmt::Display for ExecuteError<C, E, S, P>
+where
+    C: fmt::Display,
+    E: fmt::Display,
+    S: fmt::Display,
+    P: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::Command(err) => err.fmt(f),
+            ExecuteError::Event(err) => write!(f, "can not apply event: {}", err),
+            ExecuteError::Store(err) => err.fmt(f),
+            ExecuteError::Snapshot(err) => write!(f, "can not load snapshot: {}", err),
+        }
+    }
+}
+
+impl<C, E, S, P> error::Error for ExecuteError<C, E, S, P>
+where
+    C: fmt::Debug + fmt::Display,
+    E: fmt::Debug + fmt::Display,
+    S: fmt::Debug + fmt::Display,
+    P: fmt::Debug + fmt::Display,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::EventEnvelope;
+    use crate::envelope::Metadata;
+    use crate::eventstore::{
+        AppendResult, EventStore, EventStoreError, ExpectedVersion, InMemoryEventStore, LoadResult,
+        Position, VersionConflict,
+    };
+    use crate::publisher::EventPublisher;
+    use crate::repository::{ExecuteError, Repository};
+    use crate::snapshot::{InMemorySnapshotStore, Snapshot, SnapshotPolicy, SnapshotStore};
+    use crate::test_support::{
+        events_of, Add, Counter, CounterError, CounterEvent, CounterId, Subtract,
+    };
+    use crate::tracked::Tracked;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    type TestRepository = Repository<Counter, InMemoryEventStore<Counter, CounterEvent>>;
+
+    #[test]
+    fn load_replays_all_events_of_the_stream() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+        let events = vec![CounterEvent::Added(5), CounterEvent::Subtracted(2)];
+        repository
+            .event_store()
+            .append(&100, ExpectedVersion::Exact(0), &events, &Metadata::new())
+            .unwrap();
+        let expected = Counter {
+            value: 3,
+            generation: 2,
+        };
+
+        // Act
+        let result = repository.load(&100);
+
+        // Assert
+        assert_eq!(Ok(expected), result);
+    }
+
+    #[test]
+    fn execute_appends_produced_events() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+        repository.execute(&100, Add(5)).unwrap();
+
+        // Act
+        let result = repository.execute(&100, Subtract(2));
+
+        // Assert
+        assert_eq!(Ok(2), result);
+        assert_eq!(
+            vec![CounterEvent::Added(5), CounterEvent::Subtracted(2)],
+            events_of(repository.event_store().load(&100).unwrap())
+        );
+    }
+
+    #[test]
+    fn execute_records_metadata_with_produced_events() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+        let metadata = Metadata::new()
+            .with_causation_id("add-5")
+            .with_user_id("miro");
+
+        // Act
+        repository
+            .execute_with_metadata(&100, Add(5), &metadata)
+            .unwrap();
+
+        // Assert
+        let envelopes = repository.event_store().load(&100).unwrap();
+        assert_eq!(metadata, envelopes[0].metadata);
+    }
+
+    #[test]
+    fn execute_returns_command_error_and_appends_nothing() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+
+        // Act
+        let result = repository.execute(&100, Subtract(2));
+
+        // Assert
+        assert_eq!(Err(ExecuteError::Command(CounterError::BelowZero)), result);
+        assert_eq!(Ok(vec![]), repository.event_store().load(&100));
+    }
+
+    #[test]
+    fn execute_fails_when_stream_moved_on_after_loading() {
+        // Arrange
+        let repository = Repository::new(ConcurrentlyModifiedEventStore {
+            inner: InMemoryEventStore::new(),
+        });
+        let expected = Err(ExecuteError::Store(EventStoreError::Conflict(
+            VersionConflict {
+                expected: 0,
+                actual: 1,
+            },
+        )));
+
+        // Act
+        let result = repository.execute(&100, Add(5));
+
+        // Assert
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn load_replays_only_events_after_the_snapshot() {
+        // Arrange
+        let repository = Repository::with_snapshots(
+            InMemoryEventStore::<Counter, CounterEvent>::new(),
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Never,
+        );
+        let events = vec![CounterEvent::Added(5), CounterEvent::Added(2)];
+        repository
+            .event_store()
+            .append(&100, ExpectedVersion::Exact(0), &events, &Metadata::new())
+            .unwrap();
+        // deliberately different from what replaying the first event gives
+        let snapshot = Snapshot {
+            aggregate: Counter {
+                value: 40,
+                generation: 1,
+            },
+            version: 1,
+        };
+        repository
+            .snapshot_store()
+            .save_snapshot(&100, snapshot)
+            .unwrap();
+
+        // Act
+        let result = repository.load(&100);
+
+        // Assert
+        let expected = Counter {
+            value: 42,
+            generation: 2,
+        };
+        assert_eq!(Ok(expected), result);
+    }
+
+    #[test]
+    fn execute_takes_snapshot_according_to_policy() {
+        // Arrange
+        let repository = Repository::with_snapshots(
+            InMemoryEventStore::<Counter, CounterEvent>::new(),
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Every(2),
+        );
+        repository.execute(&100, Add(1)).unwrap();
+        let before_policy = repository.snapshot_store().load_snapshot(&100);
+
+        // Act
+        repository.execute(&100, Add(2)).unwrap();
+        repository.execute(&100, Add(3)).unwrap();
+
+        // Assert
+        let expected = Snapshot {
+            aggregate: Counter {
+                value: 3,
+                generation: 2,
+            },
+            version: 2,
+        };
+        assert_eq!(Ok(None), before_policy);
+        assert_eq!(
+            Ok(Some(expected)),
+            repository.snapshot_store().load_snapshot(&100)
+        );
+        assert_eq!(6, repository.load(&100).unwrap().value);
+    }
+
+    #[test]
+    fn save_snapshots_the_saved_aggregate_without_loading_it_again() {
+        // Arrange
+        let repository = Repository::with_snapshots(
+            ForgetfulEventStore,
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Every(1),
+        );
+        let mut counter = Tracked::new(Counter::default());
+        counter.record(CounterEvent::Added(5)).unwrap();
+
+        // Act
+        repository.save(&100, &mut counter).unwrap();
+
+        // Assert
+        let expected = Snapshot {
+            aggregate: Counter {
+                value: 5,
+                generation: 1,
+            },
+            version: 1,
+        };
+        assert_eq!(
+            Ok(Some(expected)),
+            repository.snapshot_store().load_snapshot(&100)
+        );
+    }
+
+    #[test]
+    fn load_replays_the_stream_when_the_snapshot_is_of_another_version_than_its_aggregate() {
+        // Arrange
+        let repository = Repository::with_snapshots(
+            InMemoryEventStore::<Counter, CounterEvent>::new(),
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Never,
+        );
+        repository.execute(&100, Add(5)).unwrap();
+        repository.execute(&100, Subtract(1)).unwrap();
+        let snapshot = Snapshot {
+            aggregate: Counter {
+                value: 5,
+                generation: 1,
+            },
+            version: 2,
+        };
+        repository
+            .snapshot_store()
+            .save_snapshot(&100, snapshot)
+            .unwrap();
+
+        // Act
+        let result = repository.load(&100);
+
+        // Assert
+        assert_eq!(
+            Ok(Counter {
+                value: 4,
+                generation: 2,
+            }),
+            result
+        );
+    }
+
+    #[test]
+    fn save_appends_recorded_events_and_stops_tracking_them() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+        repository.execute(&100, Add(5)).unwrap();
+        let mut counter = Tracked::new(repository.load(&100).unwrap());
+        counter.execute(Add(2)).unwrap();
+        counter.execute(Subtract(4)).unwrap();
+
+        // Act
+        let result = repository.save(&100, &mut counter);
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert!(counter.uncommitted().is_empty());
+        assert_eq!(3, counter.committed_version());
+        assert_eq!(
+            vec![
+                CounterEvent::Added(5),
+                CounterEvent::Added(2),
+                CounterEvent::Subtracted(4)
+            ],
+            events_of(repository.event_store().load(&100).unwrap())
+        );
+    }
+
+    #[test]
+    fn save_keeps_recorded_events_when_stream_moved_on() {
+        // Arrange
+        let repository = TestRepository::new(InMemoryEventStore::new());
+        let mut counter = Tracked::new(repository.load(&100).unwrap());
+        counter.execute(Add(2)).unwrap();
+        repository.execute(&100, Add(5)).unwrap();
+
+        // Act
+        let result = repository.save(&100, &mut counter);
+
+        // Assert
+        assert_eq!(
+            Err(EventStoreError::Conflict(VersionConflict {
+                expected: 0,
+                actual: 1,
+            })),
+            result
+        );
+        assert_eq!(&[CounterEvent::Added(2)], counter.uncommitted());
+    }
+
+    #[test]
+    fn execute_publishes_appended_events() {
+        // Arrange
+        let published = RecordingPublisher::default();
+        let repository =
+            TestRepository::new(InMemoryEventStore::new()).with_publisher(published.clone());
+        repository.execute(&100, Add(5)).unwrap();
+
+        // Act
+        let rejected = repository.execute(&100, Subtract(7));
+        repository.execute(&101, Add(1)).unwrap();
+
+        // Assert
+        assert!(rejected.is_err());
+        let published = published.0.lock().unwrap();
+        assert_eq!(
+            vec![("100", 1), ("101", 1)],
+            published
+                .iter()
+                .map(|envelope| (envelope.aggregate_id.as_str(), envelope.sequence))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(CounterEvent::Added(5), published[0].event);
+    }
+
+    #[test]
+    fn save_publishes_the_appended_events_without_loading_them_again() {
+        // Arrange
+        let published = RecordingPublisher::default();
+        let repository = Repository::new(ForgetfulEventStore).with_publisher(published.clone());
+        let mut counter = Tracked::new(Counter::default());
+        counter.execute(Add(2)).unwrap();
+        counter.execute(Add(3)).unwrap();
+        let metadata = Metadata::new().with_user_id("miro");
+
+        // Act
+        let result = repository.save_with_metadata(&100, &mut counter, &metadata);
+
+        // Assert
+        assert_eq!(Ok(2), result);
+        let published = published.0.lock().unwrap();
+        assert_eq!(
+            vec![(1, CounterEvent::Added(2)), (2, CounterEvent::Added(3))],
+            published
+                .iter()
+                .map(|envelope| (envelope.sequence, envelope.event.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some("miro"), published[1].metadata.user_id());
+    }
+
+    #[derive(Default, Clone)]
+    struct RecordingPublisher(Arc<Mutex<Vec<EventEnvelope<CounterEvent>>>>);
+
+    impl EventPublisher<CounterEvent> for RecordingPublisher {
+        fn publish(&self, envelopes: &[EventEnvelope<CounterEvent>]) {
+            self.0.lock().unwrap().extend_from_slice(envelopes);
+        }
+    }
+
+    /// Loses the events appended to it, loading an aggregate back does not give what got saved.
+    struct ForgetfulEventStore;
+
+    impl EventStore<Counter> for ForgetfulEventStore {
+        type Event = CounterEvent;
+        type Error = Infallible;
+
+        fn load(&self, _aggregate_id: &CounterId) -> LoadResult<CounterEvent, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn read_all(
+            &self,
+            _after: Position,
+            _limit: usize,
+        ) -> LoadResult<CounterEvent, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn append(
+            &self,
+            _aggregate_id: &CounterId,
+            _expected_version: ExpectedVersion,
+            events: &[CounterEvent],
+            _metadata: &Metadata,
+        ) -> AppendResult<Self::Error> {
+            Ok(events.len() as u64)
+        }
+    }
+
+    /// Simulates another handler appending to the stream between load and append.
+    struct ConcurrentlyModifiedEventStore {
+        inner: InMemoryEventStore<Counter, CounterEvent>,
+    }
+
+    impl EventStore<Counter> for ConcurrentlyModifiedEventStore {
+        type Event = CounterEvent;
+        type Error = Infallible;
+
+        fn load(&self, aggregate_id: &CounterId) -> LoadResult<CounterEvent, Self::Error> {
+            let events = self.inner.load(aggregate_id);
+            self.inner
+                .append(
+                    aggregate_id,
+                    ExpectedVersion::Any,
+                    &[CounterEvent::Added(1)],
+                    &Metadata::new(),
+                )
+                .unwrap();
+            events
+        }
+
+        fn read_all(&self, after: Position, limit: usize) -> LoadResult<CounterEvent, Self::Error> {
+            self.inner.read_all(after, limit)
+        }
+
+        fn append(
+            &self,
+            aggregate_id: &CounterId,
+            expected_version: ExpectedVersion,
+            events: &[CounterEvent],
+            metadata: &Metadata,
+        ) -> AppendResult<Self::Error> {
+            self.inner
+                .append(aggregate_id, expected_version, events, metadata)
+        }
+    }
+}
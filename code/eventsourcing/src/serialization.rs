@@ -0,0 +1,365 @@
+use crate::upcasting::{Upcaster, UpcasterChain};
+use crate::Event;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::{error, fmt};
+
+pub type SerializationResult<T> = Result<T, SerializationError>;
+
+/// Storage neutral form of an event: its type and schema version next to the encoded payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializedEvent {
+    pub event_type: String,
+    pub event_version: u32,
+    pub payload: Vec<u8>,
+}
+
+/// Encodes the payload an event carries on its own, the registry stores its type and version next
+/// to it. The `Event` derive implements it for an enum by encoding the event its variant holds.
+pub trait EventPayload {
+    fn encode_payload<F: Format>(&self) -> SerializationResult<Vec<u8>>;
+}
+
+/// Type and schema version of an event known without having one, the `Event` derive implements it
+/// for structs so the `AggregateEvent` derive can register the event of every variant.
+pub trait EventSchema {
+    const EVENT_TYPE: &'static str;
+    const EVENT_VERSION: u32;
+}
+
+/// Encoding used both for event payloads and for whole serialized events.
+pub trait Format {
+    fn encode<T: Serialize>(value: &T) -> SerializationResult<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> SerializationResult<T>;
+    fn encode_record(record: &SerializedEvent) -> SerializationResult<Vec<u8>>;
+    fn decode_record(bytes: &[u8]) -> SerializationResult<SerializedEvent>;
+}
+
+/// `{"event_type":"credited","event_version":1,"payload":{...}}`
+pub struct Json;
+
+#[derive(Serialize, Deserialize)]
+struct JsonRecord {
+    event_type: String,
+    event_version: u32,
+    payload: Box<RawValue>,
+}
+
+impl Format for Json {
+    fn encode<T: Serialize>(value: &T) -> SerializationResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(SerializationError::malformed)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> SerializationResult<T> {
+        serde_json::from_slice(bytes).map_err(SerializationError::malformed)
+    }
+
+    fn encode_record(record: &SerializedEvent) -> SerializationResult<Vec<u8>> {
+        let payload =
+            String::from_utf8(record.payload.clone()).map_err(SerializationError::malformed)?;
+
+        Json::encode(&JsonRecord {
+            event_type: record.event_type.clone(),
+            event_version: record.event_version,
+            payload: RawValue::from_string(payload).map_err(SerializationError::malformed)?,
+        })
+    }
+
+    fn decode_record(bytes: &[u8]) -> SerializationResult<SerializedEvent> {
+        let record: JsonRecord = Json::decode(bytes)?;
+
+        Ok(SerializedEvent {
+            event_type: record.event_type,
+            event_version: record.event_version,
+            payload: record.payload.get().as_bytes().to_vec(),
+        })
+    }
+}
+
+/// Compact bincode encoding, the record is the type, version and payload written one after another.
+pub struct Binary;
+
+impl Format for Binary {
+    fn encode<T: Serialize>(value: &T) -> SerializationResult<Vec<u8>> {
+        bincode::serialize(value).map_err(SerializationError::malformed)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> SerializationResult<T> {
+        bincode::deserialize(bytes).map_err(SerializationError::malformed)
+    }
+
+    fn encode_record(record: &SerializedEvent) -> SerializationResult<Vec<u8>> {
+        Binary::encode(record)
+    }
+
+    fn decode_record(bytes: &[u8]) -> SerializationResult<SerializedEvent> {
+        Binary::decode(bytes)
+    }
+}
+
+type Decoder<E> = Box<dyn Fn(&[u8]) -> SerializationResult<E> + Send + Sync>;
+
+/// Knows how to turn a payload of every registered `event_type` back into the event `E`,
+/// upcasting payloads stored in older schema versions first.
+pub struct EventRegistry<E, F> {
+    decoders: HashMap<String, (u32, Decoder<E>)>,
+    upcasters: UpcasterChain,
+    _format: PhantomData<F>,
+}
+
+impl<E, F> EventRegistry<E, F>
+where
+    E: Event + EventPayload,
+    F: Format,
+{
+    pub fn new() -> EventRegistry<E, F> {
+        EventRegistry {
+            decoders: HashMap::new(),
+            upcasters: UpcasterChain::new(),
+            _format: PhantomData,
+        }
+    }
+
+    /// Registers the payload type `T` stored under `event_type` in the given schema version,
+    /// `wrap` is usually the enum variant, e.g. `BankAccountEvent::Credited`. Panics when the
+    /// `event_type` is registered already, older versions of it are upcasted instead.
+    pub fn register<T>(mut self, event_type: &str, event_version: u32, wrap: fn(T) -> E) -> Self
+    where
+        T: DeserializeOwned + 'static,
+        E: 'static,
+    {
+        if let Some((registered, _)) = self.decoders.get(event_type) {
+            panic!(
+                "event type '{}' is registered already in version {}",
+                event_type, registered
+            );
+        }
+        let decoder: Decoder<E> = Box::new(move |bytes| F::decode::<T>(bytes).map(wrap));
+        self.decoders
+            .insert(event_type.to_owned(), (event_version, decoder));
+        self
+    }
+
+    /// Adds an upcaster bringing an older `event_type`/version pair closer to the registered one.
+    pub fn upcaster<U: Upcaster + 'static>(mut self, upcaster: U) -> Self {
+        self.upcasters.push(upcaster);
+        self
+    }
+
+    pub fn serialize(&self, event: &E) -> SerializationResult<SerializedEvent> {
+        Ok(SerializedEvent {
+            event_type: event.event_type().to_owned(),
+            event_version: event.event_version(),
+            payload: event.encode_payload::<F>()?,
+        })
+    }
+
+    pub fn deserialize(&self, serialized: &SerializedEvent) -> SerializationResult<E> {
+        let upcasted;
+        let serialized = if self
+            .upcasters
+            .can_upcast(&serialized.event_type, serialized.event_version)
+        {
+            upcasted = self.upcasters.upcast(serialized.clone())?;
+            &upcasted
+        } else {
+            serialized
+        };
+        let (version, decoder) = self
+            .decoders
+            .get(&serialized.event_type)
+            .ok_or_else(|| SerializationError::UnknownEventType(serialized.event_type.clone()))?;
+
+        if *version != serialized.event_version {
+            return Err(SerializationError::UnsupportedVersion {
+                event_type: serialized.event_type.clone(),
+                event_version: serialized.event_version,
+            });
+        }
+
+        decoder(&serialized.payload)
+    }
+
+    pub fn to_bytes(&self, event: &E) -> SerializationResult<Vec<u8>> {
+        F::encode_record(&self.serialize(event)?)
+    }
+
+    pub fn from_bytes(&self, bytes: &[u8]) -> SerializationResult<E> {
+        self.deserialize(&F::decode_record(bytes)?)
+    }
+}
+
+impl<E, F> Default for EventRegistry<E, F>
+where
+    E: Event + EventPayload,
+    F: Format,
+{
+    fn default() -> Self {
+        EventRegistry::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerializationError {
+    UnknownEventType(String),
+    UnsupportedVersion {
+        event_type: String,
+        event_version: u32,
+    },
+    Malformed(String),
+}
+
+impl SerializationError {
+    fn malformed<E: fmt::Display>(err: E) -> SerializationError {
+        SerializationError::Malformed(err.to_string())
+    }
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerializationError::UnknownEventType(event_type) => {
+                write!(f, "no event registered for type '{}'", event_type)
+            }
+            SerializationError::UnsupportedVersion {
+                event_type,
+                event_version,
+            } => write!(
+                f,
+                "version {} of event '{}' is not supported",
+                event_version, event_type
+            ),
+            SerializationError::Malformed(reason) => write!(f, "malformed event: {}", reason),
+        }
+    }
+}
+
+impl error::Error for SerializationError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::serialization::{
+        Binary, EventRegistry, Format, Json, SerializationError, SerializedEvent,
+    };
+    use crate::test_support::{CounterEvent, Reset};
+
+    fn registry<F: Format>() -> EventRegistry<CounterEvent, F> {
+        EventRegistry::new()
+            .register("added", 1, CounterEvent::Added)
+            .register("subtracted", 1, CounterEvent::Subtracted)
+            .register("reset", 2, CounterEvent::Reset)
+    }
+
+    #[test]
+    fn events_are_serialized_with_type_and_version() {
+        // Arrange
+        let event = CounterEvent::Reset(Reset {
+            reason: "new year".into(),
+        });
+        let expected = SerializedEvent {
+            event_type: "reset".into(),
+            event_version: 2,
+            payload: br#"{"reason":"new year"}"#.to_vec(),
+        };
+
+        // Act
+        let result = registry::<Json>().serialize(&event);
+
+        // Assert
+        assert_eq!(Ok(expected), result);
+    }
+
+    #[test]
+    fn json_wire_format_is_stable() {
+        // Arrange
+        let expected = r#"{"event_type":"added","event_version":1,"payload":5}"#;
+
+        // Act
+        let bytes = registry::<Json>()
+            .to_bytes(&CounterEvent::Added(5))
+            .unwrap();
+
+        // Assert
+        assert_eq!(expected, String::from_utf8(bytes).unwrap());
+    }
+
+    #[test]
+    fn events_survive_a_round_trip_in_every_format() {
+        let events = vec![
+            CounterEvent::Added(5),
+            CounterEvent::Subtracted(2),
+            CounterEvent::Reset(Reset {
+                reason: "audit".into(),
+            }),
+        ];
+
+        for event in events {
+            let json = registry::<Json>();
+            let binary = registry::<Binary>();
+
+            let from_json = json.from_bytes(&json.to_bytes(&event).unwrap());
+            let from_binary = binary.from_bytes(&binary.to_bytes(&event).unwrap());
+
+            assert_eq!(Ok(event.clone()), from_json);
+            assert_eq!(Ok(event), from_binary);
+        }
+    }
+
+    #[test]
+    fn binary_form_is_more_compact() {
+        let event = CounterEvent::Reset(Reset {
+            reason: "audit".into(),
+        });
+
+        let json = registry::<Json>().to_bytes(&event).unwrap();
+        let binary = registry::<Binary>().to_bytes(&event).unwrap();
+
+        assert!(binary.len() < json.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "event type 'reset' is registered already in version 2")]
+    fn registering_an_event_type_twice_panics() {
+        registry::<Json>().register("reset", 3, CounterEvent::Reset);
+    }
+
+    #[test]
+    fn unknown_event_type_is_rejected() {
+        // Arrange
+        let serialized = SerializedEvent {
+            event_type: "multiplied".into(),
+            event_version: 1,
+            payload: b"2".to_vec(),
+        };
+        let expected = Err(SerializationError::UnknownEventType("multiplied".into()));
+
+        // Act
+        let result = registry::<Json>().deserialize(&serialized);
+
+        // Assert
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn unregistered_version_is_rejected() {
+        // Arrange
+        let serialized = SerializedEvent {
+            event_type: "reset".into(),
+            event_version: 1,
+            payload: b"{}".to_vec(),
+        };
+        let expected = Err(SerializationError::UnsupportedVersion {
+            event_type: "reset".into(),
+            event_version: 1,
+        });
+
+        // Act
+        let result = registry::<Json>().deserialize(&serialized);
+
+        // Assert
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::eventstore::Version;
+use crate::{Aggregate, CqrsError};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::RwLock;
+
+/// State of an aggregate after the event with the given sequence got applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<A> {
+    pub aggregate: A,
+    pub version: Version,
+}
+
+impl<A: Aggregate> Snapshot<A> {
+    /// Snapshot of the aggregate at the version it is at.
+    pub fn of(aggregate: A) -> Snapshot<A> {
+        let version = aggregate.version();
+        Snapshot { aggregate, version }
+    }
+}
+
+pub trait SnapshotStore<A: Aggregate> {
+    type Error: CqrsError;
+
+    /// Returns the latest snapshot of the stream, if one was taken yet.
+    fn load_snapshot(&self, aggregate_id: &A::Id) -> Result<Option<Snapshot<A>>, Self::Error>;
+
+    /// Replaces the snapshot of the stream.
+    fn save_snapshot(&self, aggregate_id: &A::Id, snapshot: Snapshot<A>)
+        -> Result<(), Self::Error>;
+}
+
+/// When the repository takes a new snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotPolicy {
+    Never,
+    /// Every time the stream version crosses a multiple of the given number of events.
+    Every(Version),
+}
+
+impl SnapshotPolicy {
+    pub fn should_snapshot(self, previous: Version, current: Version) -> bool {
+        match self {
+            SnapshotPolicy::Every(events) if events > 0 => current / events > previous / events,
+            _ => false,
+        }
+    }
+}
+
+/// Store of a repository which does not use snapshots, every load replays the whole stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoSnapshots;
+
+impl<A: Aggregate> SnapshotStore<A> for NoSnapshots {
+    type Error = Infallible;
+
+    fn load_snapshot(&self, _aggregate_id: &A::Id) -> Result<Option<Snapshot<A>>, Self::Error> {
+        Ok(None)
+    }
+
+    fn save_snapshot(
+        &self,
+        _aggregate_id: &A::Id,
+        _snapshot: Snapshot<A>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Keeps the latest snapshot of every stream of the aggregate `A` in memory.
+pub struct InMemorySnapshotStore<A> {
+    snapshots: RwLock<HashMap<String, Snapshot<A>>>,
+}
+
+impl<A> InMemorySnapshotStore<A> {
+    pub fn new() -> InMemorySnapshotStore<A> {
+        InMemorySnapshotStore {
+            snapshots: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<A> Default for InMemorySnapshotStore<A> {
+    fn default() -> Self {
+        InMemorySnapshotStore::new()
+    }
+}
+
+impl<A: Aggregate + Clone> SnapshotStore<A> for InMemorySnapshotStore<A> {
+    type Error = Infallible;
+
+    fn load_snapshot(&self, aggregate_id: &A::Id) -> Result<Option<Snapshot<A>>, Self::Error> {
+        let snapshots = self.snapshots.read().unwrap();
+
+        Ok(snapshots.get(&aggregate_id.to_string()).cloned())
+    }
+
+    fn save_snapshot(
+        &self,
+        aggregate_id: &A::Id,
+        snapshot: Snapshot<A>,
+    ) -> Result<(), Self::Error> {
+        let mut snapshots = self.snapshots.write().unwrap();
+        snapshots.insert(aggregate_id.to_string(), snapshot);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::{InMemorySnapshotStore, Snapshot, SnapshotPolicy, SnapshotStore};
+    use crate::test_support::Counter;
+
+    #[test]
+    fn every_policy_triggers_when_crossing_a_multiple() {
+        let policy = SnapshotPolicy::Every(10);
+
+        assert!(!policy.should_snapshot(0, 9));
+        assert!(policy.should_snapshot(9, 10));
+        assert!(policy.should_snapshot(8, 12));
+        assert!(!policy.should_snapshot(10, 19));
+        assert!(!SnapshotPolicy::Never.should_snapshot(0, 100));
+        assert!(!SnapshotPolicy::Every(0).should_snapshot(0, 100));
+    }
+
+    #[test]
+    fn latest_snapshot_replaces_previous_one() {
+        // Arrange
+        let store = InMemorySnapshotStore::new();
+        let snapshot = |value, version| Snapshot {
+            aggregate: Counter {
+                value,
+                generation: version,
+            },
+            version,
+        };
+        store.save_snapshot(&100, snapshot(5, 2)).unwrap();
+
+        // Act
+        store.save_snapshot(&100, snapshot(7, 4)).unwrap();
+
+        // Assert
+        assert_eq!(Ok(Some(snapshot(7, 4))), store.load_snapshot(&100));
+        assert_eq!(Ok(None), store.load_snapshot(&101));
+    }
+}
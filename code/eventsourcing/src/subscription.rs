@@ -0,0 +1,516 @@
+use crate::envelope::{EventEnvelope, RecordedEvent};
+use crate::eventstore::{EventLog, EventStore, EventStoreError, Position, Version};
+use crate::{Aggregate, CqrsError};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/// Streams a subscription delivers events of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionTarget {
+    /// A single stream, e.g. one bank account, followed by its version.
+    Stream(String),
+    /// All streams of one aggregate type, e.g. all bank accounts, followed by position.
+    AggregateType(&'static str),
+    /// All streams of every aggregate type sharing one log, e.g. bank accounts and customers,
+    /// followed by their global position.
+    All,
+}
+
+/// Reads the events of a target a subscription delivers.
+pub trait SubscriptionSource {
+    type Event;
+    type Error: CqrsError;
+
+    fn target(&self) -> SubscriptionTarget;
+
+    /// Loads up to `limit` events past the checkpoint, in the order they get delivered in.
+    fn read(
+        &self,
+        checkpoint: u64,
+        limit: usize,
+    ) -> Result<Vec<Self::Event>, EventStoreError<Self::Error>>;
+
+    /// Checkpoint to read past once the event got delivered.
+    fn checkpoint(&self, event: &Self::Event) -> u64;
+
+    /// Blocks until events may have been appended or the timeout passed.
+    fn wait_for_append(&self, timeout: Duration);
+}
+
+/// Reads a single stream from the store of its aggregate type, past a version of the stream.
+pub struct StreamSource<A: Aggregate, S> {
+    event_store: S,
+    aggregate_id: A::Id,
+}
+
+impl<A, S> SubscriptionSource for StreamSource<A, S>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    type Event = EventEnvelope<S::Event>;
+    type Error = S::Error;
+
+    fn target(&self) -> SubscriptionTarget {
+        SubscriptionTarget::Stream(self.aggregate_id.to_string())
+    }
+
+    fn read(
+        &self,
+        checkpoint: u64,
+        limit: usize,
+    ) -> Result<Vec<Self::Event>, EventStoreError<Self::Error>> {
+        let mut envelopes = self.event_store.load_from(&self.aggregate_id, checkpoint)?;
+        envelopes.truncate(limit);
+        Ok(envelopes)
+    }
+
+    fn checkpoint(&self, envelope: &Self::Event) -> u64 {
+        envelope.sequence
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        self.event_store.wait_for_append(timeout)
+    }
+}
+
+/// Reads all streams from the store of the aggregate type, past a position.
+pub struct AggregateTypeSource<A, S> {
+    event_store: S,
+    _aggregate: PhantomData<A>,
+}
+
+impl<A, S> SubscriptionSource for AggregateTypeSource<A, S>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    type Event = EventEnvelope<S::Event>;
+    type Error = S::Error;
+
+    fn target(&self) -> SubscriptionTarget {
+        SubscriptionTarget::AggregateType(A::aggregate_type())
+    }
+
+    fn read(
+        &self,
+        checkpoint: u64,
+        limit: usize,
+    ) -> Result<Vec<Self::Event>, EventStoreError<Self::Error>> {
+        self.event_store.read_all(checkpoint, limit)
+    }
+
+    fn checkpoint(&self, envelope: &Self::Event) -> u64 {
+        envelope.position
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        self.event_store.wait_for_append(timeout)
+    }
+}
+
+/// Reads the log shared by all aggregate types, past a global position. Events stay encoded as no
+/// single registry can decode all of them.
+pub struct AllSource<L> {
+    log: L,
+}
+
+impl<L: EventLog> SubscriptionSource for AllSource<L> {
+    type Event = RecordedEvent;
+    type Error = L::Error;
+
+    fn target(&self) -> SubscriptionTarget {
+        SubscriptionTarget::All
+    }
+
+    fn read(
+        &self,
+        checkpoint: u64,
+        limit: usize,
+    ) -> Result<Vec<Self::Event>, EventStoreError<Self::Error>> {
+        self.log.read_log(checkpoint, limit)
+    }
+
+    fn checkpoint(&self, recorded: &Self::Event) -> u64 {
+        recorded.position
+    }
+
+    fn wait_for_append(&self, timeout: Duration) {
+        self.log.wait_for_append(timeout)
+    }
+}
+
+/// Delivers events appended to the target streams, first the ones already stored after the
+/// starting checkpoint and then live ones as they get appended.
+///
+/// Every batch is read from the store past the checkpoint of the previous one, so there is no
+/// switch between the history and a live feed where events could be missed or delivered twice.
+pub struct Subscription<R> {
+    source: R,
+    checkpoint: u64,
+    batch_size: usize,
+    poll_interval: Duration,
+}
+
+impl<A, S> Subscription<StreamSource<A, S>>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    /// Subscribes to a single stream, starting after the given version of it.
+    pub fn to_stream(
+        event_store: S,
+        aggregate_id: A::Id,
+        after: Version,
+    ) -> Subscription<StreamSource<A, S>> {
+        let source = StreamSource {
+            event_store,
+            aggregate_id,
+        };
+
+        Subscription::new(source, after)
+    }
+}
+
+impl<A, S> Subscription<AggregateTypeSource<A, S>>
+where
+    A: Aggregate,
+    S: EventStore<A>,
+{
+    /// Subscribes to all streams of the aggregate type, starting after the event at the given
+    /// position.
+    pub fn to_aggregate_type(
+        event_store: S,
+        after: Position,
+    ) -> Subscription<AggregateTypeSource<A, S>> {
+        let source = AggregateTypeSource {
+            event_store,
+            _aggregate: PhantomData,
+        };
+
+        Subscription::new(source, after)
+    }
+}
+
+impl<L: EventLog> Subscription<AllSource<L>> {
+    /// Subscribes to all streams of every aggregate type in the log, starting after the event at
+    /// the given global position.
+    pub fn to_all(log: L, after: Position) -> Subscription<AllSource<L>> {
+        Subscription::new(AllSource { log }, after)
+    }
+}
+
+impl<R: SubscriptionSource> Subscription<R> {
+    pub fn new(source: R, after: u64) -> Subscription<R> {
+        Subscription {
+            source,
+            checkpoint: after,
+            batch_size: 100,
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+
+    /// How many events to read from the store at once.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Subscription<R> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// How long to wait at most before looking for new events again, stores which can tell when
+    /// events got appended usually wake the subscription up sooner.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Subscription<R> {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn target(&self) -> SubscriptionTarget {
+        self.source.target()
+    }
+
+    /// Version of the stream for a stream subscription and position of the last event read
+    /// otherwise, save it to resume the subscription later on.
+    pub fn checkpoint(&self) -> u64 {
+        self.checkpoint
+    }
+
+    /// Returns the next batch of events appended after the previous one without waiting for new
+    /// ones.
+    pub fn poll(&mut self) -> Result<Vec<R::Event>, EventStoreError<R::Error>> {
+        let events = self.source.read(self.checkpoint, self.batch_size)?;
+        if let Some(last) = events.last() {
+            self.checkpoint = self.source.checkpoint(last);
+        }
+
+        Ok(events)
+    }
+
+    /// Returns the next batch of events appended after the previous one, waiting up to `timeout`
+    /// for new ones if there are none yet. An empty batch means nothing got appended in time.
+    pub fn next_batch(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<R::Event>, EventStoreError<R::Error>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let events = self.poll()?;
+            let now = Instant::now();
+            if !events.is_empty() || now >= deadline {
+                return Ok(events);
+            }
+
+            self.source
+                .wait_for_append(self.poll_interval.min(deadline - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::Metadata;
+    use crate::eventstore::{EventStore, ExpectedVersion, InMemoryEventStore};
+    use crate::subscription::Subscription;
+    use crate::test_support::{events_of, Counter, CounterEvent};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn append(
+        event_store: &InMemoryEventStore<Counter, CounterEvent>,
+        id: u64,
+        events: &[CounterEvent],
+    ) {
+        event_store
+            .append(&id, ExpectedVersion::Any, events, &Metadata::new())
+            .unwrap();
+    }
+
+    #[test]
+    fn stream_subscription_delivers_only_its_stream_after_given_version() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(1), CounterEvent::Added(2)],
+        );
+        append(&event_store, 101, &[CounterEvent::Added(3)]);
+        let mut subscription =
+            Subscription::to_stream(Arc::clone(&event_store), 100, 1).with_batch_size(1);
+
+        // Act
+        let history = subscription.poll().unwrap();
+        append(&event_store, 101, &[CounterEvent::Added(4)]);
+        append(&event_store, 100, &[CounterEvent::Added(5)]);
+        let live = subscription.next_batch(Duration::from_secs(1)).unwrap();
+
+        // Assert
+        assert_eq!(vec![CounterEvent::Added(2)], events_of(history));
+        assert_eq!(vec![CounterEvent::Added(5)], events_of(live));
+        assert_eq!(3, subscription.checkpoint());
+    }
+
+    #[test]
+    fn aggregate_type_subscription_resumes_from_position() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        append(
+            &event_store,
+            100,
+            &[CounterEvent::Added(1), CounterEvent::Added(2)],
+        );
+        append(&event_store, 101, &[CounterEvent::Added(3)]);
+        let mut subscription = Subscription::to_aggregate_type(Arc::clone(&event_store), 1);
+
+        // Act
+        let history = subscription.poll().unwrap();
+        let nothing_new = subscription.next_batch(Duration::from_millis(10)).unwrap();
+
+        // Assert
+        assert_eq!(
+            vec![CounterEvent::Added(2), CounterEvent::Added(3)],
+            events_of(history)
+        );
+        assert!(nothing_new.is_empty());
+    }
+
+    #[test]
+    fn events_appended_during_catch_up_are_delivered_exactly_once() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        for amount in 0..50 {
+            append(
+                &event_store,
+                100 + amount % 3,
+                &[CounterEvent::Added(amount)],
+            );
+        }
+        let writer = {
+            let event_store = Arc::clone(&event_store);
+            thread::spawn(move || {
+                for amount in 50..100 {
+                    append(
+                        &event_store,
+                        100 + amount % 3,
+                        &[CounterEvent::Added(amount)],
+                    );
+                }
+            })
+        };
+        let mut subscription = Subscription::to_aggregate_type(Arc::clone(&event_store), 0)
+            .with_batch_size(7)
+            .with_poll_interval(Duration::from_millis(1));
+        let mut delivered = Vec::new();
+
+        // Act
+        while delivered.len() < 100 {
+            let batch = subscription.next_batch(Duration::from_secs(1)).unwrap();
+            assert!(!batch.is_empty(), "subscription stalled");
+            delivered.extend(events_of(batch));
+        }
+        writer.join().unwrap();
+
+        // Assert
+        let amounts: Vec<_> = delivered
+            .into_iter()
+            .map(|event| match event {
+                CounterEvent::Added(amount) => amount,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!((0..100).collect::<Vec<_>>(), amounts);
+        assert!(subscription.poll().unwrap().is_empty());
+    }
+
+    /// The log is only shared by stores keeping their events in the same database.
+    #[cfg(feature = "sqlite")]
+    mod all {
+        use crate::envelope::Metadata;
+        use crate::eventstore::{EventStore, ExpectedVersion, SqliteEventStore, Version};
+        use crate::serialization::{EventRegistry, Json};
+        use crate::subscription::{Subscription, SubscriptionTarget};
+        use crate::test_support::{Counter, CounterEvent};
+        use crate::{Aggregate, AggregateEvent, AggregateId};
+        use std::convert::Infallible;
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use tempfile::TempDir;
+
+        /// Second aggregate type next to the counter, reusing its events.
+        #[derive(Debug, Default)]
+        struct Gauge {
+            generation: u64,
+        }
+
+        impl Aggregate for Gauge {
+            type Id = u64;
+
+            fn aggregate_type() -> &'static str {
+                "Gauge"
+            }
+
+            fn id(&self) -> Option<&u64> {
+                None
+            }
+
+            fn version(&self) -> Version {
+                self.generation
+            }
+
+            fn increment_generation(&mut self) {
+                self.generation += 1;
+            }
+        }
+
+        impl AggregateId<Gauge> for u64 {}
+
+        impl AggregateEvent<Gauge> for CounterEvent {
+            type Error = Infallible;
+
+            fn apply_to(self, _aggregate: &mut Gauge) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        fn registry() -> EventRegistry<CounterEvent, Json> {
+            EventRegistry::new()
+                .register("added", 1, CounterEvent::Added)
+                .register("subtracted", 1, CounterEvent::Subtracted)
+                .register("reset", 2, CounterEvent::Reset)
+        }
+
+        fn append<S: EventStore<A, Event = CounterEvent>, A: Aggregate<Id = u64>>(
+            event_store: &S,
+            id: u64,
+            amount: u64,
+        ) {
+            event_store
+                .append(
+                    &id,
+                    ExpectedVersion::Any,
+                    &[CounterEvent::Added(amount)],
+                    &Metadata::new(),
+                )
+                .unwrap();
+        }
+
+        #[test]
+        fn all_subscription_delivers_every_aggregate_type_by_global_position() {
+            // Arrange
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("events.sqlite");
+            let counters =
+                Arc::new(SqliteEventStore::<Counter, _, _>::open(&path, registry()).unwrap());
+            let gauges = SqliteEventStore::<Gauge, _, _>::open(&path, registry()).unwrap();
+            append(&counters, 100, 1);
+            append(&gauges, 100, 2);
+            append(&counters, 101, 3);
+            let mut subscription = Subscription::to_all(Arc::clone(&counters), 1);
+
+            // Act
+            let history = subscription.poll().unwrap();
+            append(&gauges, 100, 4);
+            let live = subscription.next_batch(Duration::from_secs(1)).unwrap();
+
+            // Assert
+            assert_eq!(SubscriptionTarget::All, subscription.target());
+            assert_eq!(
+                vec![("Gauge", 2), ("Counter", 3)],
+                history
+                    .iter()
+                    .map(|recorded| (recorded.aggregate_type.as_str(), recorded.position))
+                    .collect::<Vec<_>>()
+            );
+            let gauge = live[0].clone().decode::<Gauge, _, _>(&registry()).unwrap();
+            assert_eq!(("Gauge", 2), (gauge.aggregate_type, gauge.sequence));
+            assert_eq!(CounterEvent::Added(4), gauge.event);
+            assert_eq!(4, subscription.checkpoint());
+        }
+
+        #[test]
+        fn all_subscription_wakes_up_on_append_before_its_poll_interval() {
+            // Arrange
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("events.sqlite");
+            let counters =
+                Arc::new(SqliteEventStore::<Counter, _, _>::open(&path, registry()).unwrap());
+            let gauges = SqliteEventStore::<Gauge, _, _>::open(&path, registry()).unwrap();
+            let mut subscription = Subscription::to_all(Arc::clone(&counters), 0)
+                .with_poll_interval(Duration::from_secs(10));
+            let appender = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                append(&gauges, 100, 1);
+            });
+            let started = Instant::now();
+
+            // Act
+            let live = subscription.next_batch(Duration::from_secs(10)).unwrap();
+
+            // Assert
+            appender.join().unwrap();
+            assert_eq!(1, live.len());
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Minimal aggregate used by the unit tests of the framework itself.
+
+use crate::envelope::EventEnvelope;
+use crate::eventstore::Version;
+use crate::serialization::{EventPayload, Format, SerializationResult};
+use crate::{Aggregate, AggregateCommand, AggregateEvent, AggregateId, Event};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub type CounterId = u64;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Counter {
+    pub value: u64,
+    pub generation: u64,
+}
+
+impl Aggregate for Counter {
+    type Id = CounterId;
+
+    fn aggregate_type() -> &'static str {
+        "Counter"
+    }
+
+    /// Events of the counter do not carry its id.
+    fn id(&self) -> Option<&CounterId> {
+        None
+    }
+
+    fn version(&self) -> Version {
+        self.generation
+    }
+
+    fn increment_generation(&mut self) {
+        self.generation += 1;
+    }
+}
+
+impl AggregateId<Counter> for CounterId {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CounterEvent {
+    Added(u64),
+    Subtracted(u64),
+    Reset(Reset),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reset {
+    pub reason: String,
+}
+
+impl Event for CounterEvent {
+    fn event_type(&self) -> &'static str {
+        match *self {
+            CounterEvent::Added(_) => "added",
+            CounterEvent::Subtracted(_) => "subtracted",
+            CounterEvent::Reset(_) => "reset",
+        }
+    }
+
+    fn event_version(&self) -> u32 {
+        match *self {
+            CounterEvent::Reset(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl EventPayload for CounterEvent {
+    fn encode_payload<F: Format>(&self) -> SerializationResult<Vec<u8>> {
+        match self {
+            CounterEvent::Added(amount) | CounterEvent::Subtracted(amount) => F::encode(amount),
+            CounterEvent::Reset(reset) => F::encode(reset),
+        }
+    }
+}
+
+impl AggregateEvent<Counter> for CounterEvent {
+    type Error = CounterError;
+
+    fn apply_to(self, aggregate: &mut Counter) -> Result<(), Self::Error> {
+        match self {
+            CounterEvent::Added(amount) => aggregate.value += amount,
+            CounterEvent::Subtracted(amount) if amount <= aggregate.value => {
+                aggregate.value -= amount
+            }
+            CounterEvent::Subtracted(_) => return Err(CounterError::BelowZero),
+            CounterEvent::Reset(_) => aggregate.value = 0,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterError {
+    BelowZero,
+}
+
+impl fmt::Display for CounterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("counter can not go below zero")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Add(pub u64);
+
+impl AggregateCommand<Counter> for Add {
+    type Error = CounterError;
+    type Event = CounterEvent;
+    type Events = Vec<Self::Event>;
+
+    fn execute_on(self, _aggregate: &Counter) -> Result<Self::Events, Self::Error> {
+        Ok(vec![CounterEvent::Added(self.0)])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtract(pub u64);
+
+impl AggregateCommand<Counter> for Subtract {
+    type Error = CounterError;
+    type Event = CounterEvent;
+    type Events = Vec<Self::Event>;
+
+    fn execute_on(self, aggregate: &Counter) -> Result<Self::Events, Self::Error> {
+        if self.0 > aggregate.value {
+            return Err(CounterError::BelowZero);
+        }
+        Ok(vec![CounterEvent::Subtracted(self.0)])
+    }
+}
+
+pub fn events_of<E: Event>(envelopes: Vec<EventEnvelope<E>>) -> Vec<E> {
+    envelopes
+        .into_iter()
+        .map(|envelope| envelope.event)
+        .collect()
+}
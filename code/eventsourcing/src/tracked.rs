@@ -0,0 +1,177 @@
+use crate::eventstore::Version;
+use crate::{Aggregate, AggregateCommand, AggregateEvent};
+use std::ops::Deref;
+use std::{error, fmt};
+
+/// Aggregate together with the events recorded on it which are not appended to its stream yet,
+/// so the aggregate itself stays pure state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tracked<A, E> {
+    aggregate: A,
+    uncommitted: Vec<E>,
+}
+
+impl<A, E> Tracked<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Starts tracking the aggregate as it is now, e.g. just rehydrated from its stream.
+    pub fn new(aggregate: A) -> Tracked<A, E> {
+        Tracked {
+            aggregate,
+            uncommitted: Vec::new(),
+        }
+    }
+
+    pub fn uncommitted(&self) -> &[E] {
+        &self.uncommitted
+    }
+
+    /// Version of the stream the events recorded since have to be appended to.
+    pub fn committed_version(&self) -> Version {
+        self.aggregate.version() - self.uncommitted.len() as Version
+    }
+
+    /// Hands over the recorded events, which are not tracked from then on.
+    pub fn take_uncommitted(&mut self) -> Vec<E> {
+        self.uncommitted.split_off(0)
+    }
+
+    pub fn into_inner(self) -> A {
+        self.aggregate
+    }
+}
+
+impl<A, E> Tracked<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Clone,
+{
+    /// Executes the command and applies and records the events it produced. When applying one of
+    /// them fails the aggregate is left half way through and should not be used any more.
+    pub fn execute<C>(&mut self, command: C) -> Result<(), TrackedError<C::Error, E::Error>>
+    where
+        C: AggregateCommand<A, Event = E>,
+    {
+        let events = self
+            .aggregate
+            .execute(command)
+            .map_err(TrackedError::Command)?;
+
+        for event in events {
+            self.record(event).map_err(TrackedError::Event)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the event and records it as not appended yet.
+    pub fn record(&mut self, event: E) -> Result<(), E::Error> {
+        self.aggregate.apply(event.clone())?;
+        self.uncommitted.push(event);
+        Ok(())
+    }
+}
+
+impl<A, E> Default for Tracked<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    fn default() -> Self {
+        Tracked::new(A::default())
+    }
+}
+
+impl<A, E> Deref for Tracked<A, E> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.aggregate
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackedError<C, E> {
+    Command(C),
+    Event(E),
+}
+
+impl<C, E> fmt::Display for TrackedError<C, E>
+where
+    C: fmt::Display,
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrackedError::Command(err) => err.fmt(f),
+            TrackedError::Event(err) => write!(f, "can not apply produced event: {}", err),
+        }
+    }
+}
+
+impl<C, E> error::Error for TrackedError<C, E>
+where
+    C: fmt::Debug + fmt::Display,
+    E: fmt::Debug + fmt::Display,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{Add, Counter, CounterError, CounterEvent, Subtract};
+    use crate::tracked::{Tracked, TrackedError};
+    use crate::Aggregate;
+
+    type TrackedCounter = Tracked<Counter, CounterEvent>;
+
+    #[test]
+    fn execute_applies_and_records_produced_events() {
+        // Arrange
+        let mut counter = TrackedCounter::new(Counter {
+            value: 1,
+            generation: 1,
+        });
+
+        // Act
+        counter.execute(Add(5)).unwrap();
+        counter.execute(Subtract(2)).unwrap();
+
+        // Assert
+        assert_eq!((4, 3), (counter.value, counter.version()));
+        assert_eq!(1, counter.committed_version());
+        assert_eq!(
+            &[CounterEvent::Added(5), CounterEvent::Subtracted(2)],
+            counter.uncommitted()
+        );
+    }
+
+    #[test]
+    fn rejected_command_records_nothing() {
+        // Arrange
+        let mut counter = TrackedCounter::default();
+
+        // Act
+        let result = counter.execute(Subtract(2));
+
+        // Assert
+        assert_eq!(Err(TrackedError::Command(CounterError::BelowZero)), result);
+        assert!(counter.uncommitted().is_empty());
+    }
+
+    #[test]
+    fn taken_events_are_no_longer_tracked() {
+        // Arrange
+        let mut counter = TrackedCounter::default();
+        counter.record(CounterEvent::Added(3)).unwrap();
+
+        // Act
+        let taken = counter.take_uncommitted();
+
+        // Assert
+        assert_eq!(vec![CounterEvent::Added(3)], taken);
+        assert!(counter.uncommitted().is_empty());
+        assert_eq!(1, counter.committed_version());
+        assert_eq!(3, counter.into_inner().value);
+    }
+}
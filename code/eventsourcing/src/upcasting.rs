@@ -0,0 +1,303 @@
+use crate::serialization::{Format, SerializationError, SerializationResult, SerializedEvent};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+/// Transforms a stored payload of an older schema into a newer one, before it gets deserialized.
+pub trait Upcaster: Send + Sync {
+    fn can_upcast(&self, event_type: &str, event_version: u32) -> bool;
+    fn upcast(&self, event: SerializedEvent) -> SerializationResult<SerializedEvent>;
+}
+
+/// Upcasters applied one after another until none of them knows what to do with the event,
+/// so `credited` v1 can become v2 and then v3 without a dedicated v1 to v3 upcaster. Renaming
+/// upcasters leading back to a type and version the event already was in are rejected.
+#[derive(Default)]
+pub struct UpcasterChain {
+    upcasters: Vec<Box<dyn Upcaster>>,
+}
+
+impl UpcasterChain {
+    pub fn new() -> UpcasterChain {
+        UpcasterChain {
+            upcasters: Vec::new(),
+        }
+    }
+
+    pub fn push<U: Upcaster + 'static>(&mut self, upcaster: U) {
+        self.upcasters.push(Box::new(upcaster));
+    }
+
+    pub fn can_upcast(&self, event_type: &str, event_version: u32) -> bool {
+        self.upcasters
+            .iter()
+            .any(|u| u.can_upcast(event_type, event_version))
+    }
+
+    pub fn upcast(&self, mut event: SerializedEvent) -> SerializationResult<SerializedEvent> {
+        let mut seen = HashSet::new();
+        seen.insert((event.event_type.clone(), event.event_version));
+
+        while let Some(upcaster) = self
+            .upcasters
+            .iter()
+            .find(|u| u.can_upcast(&event.event_type, event.event_version))
+        {
+            let upcasted = upcaster.upcast(event.clone())?;
+
+            if upcasted.event_type == event.event_type
+                && upcasted.event_version <= event.event_version
+            {
+                return Err(SerializationError::Malformed(format!(
+                    "upcaster of '{}' v{} did not move the event forward",
+                    event.event_type, event.event_version
+                )));
+            }
+            if !seen.insert((upcasted.event_type.clone(), upcasted.event_version)) {
+                return Err(SerializationError::Malformed(format!(
+                    "upcaster of '{}' v{} leads back to '{}' v{} in a cycle",
+                    event.event_type,
+                    event.event_version,
+                    upcasted.event_type,
+                    upcasted.event_version
+                )));
+            }
+            event = upcasted;
+        }
+
+        Ok(event)
+    }
+}
+
+/// Decodes the payload as `Old`, converts it and stores it as `New` under the next version,
+/// with the `Json` format `Old` can simply be a `serde_json::Value`.
+pub struct PayloadUpcaster<F, Old, New> {
+    event_type: String,
+    from_version: u32,
+    target_type: String,
+    convert: fn(Old) -> New,
+    _format: PhantomData<fn() -> F>,
+}
+
+impl<F, Old, New> PayloadUpcaster<F, Old, New> {
+    pub fn new(event_type: &str, from_version: u32, convert: fn(Old) -> New) -> Self {
+        PayloadUpcaster {
+            event_type: event_type.to_owned(),
+            from_version,
+            target_type: event_type.to_owned(),
+            convert,
+            _format: PhantomData,
+        }
+    }
+
+    /// Stores the upcasted payload under a different event type, starting again at version 1.
+    pub fn renamed(
+        event_type: &str,
+        from_version: u32,
+        target_type: &str,
+        convert: fn(Old) -> New,
+    ) -> Self {
+        PayloadUpcaster {
+            target_type: target_type.to_owned(),
+            ..PayloadUpcaster::new(event_type, from_version, convert)
+        }
+    }
+}
+
+impl<F, Old, New> Upcaster for PayloadUpcaster<F, Old, New>
+where
+    F: Format,
+    Old: DeserializeOwned,
+    New: Serialize,
+{
+    fn can_upcast(&self, event_type: &str, event_version: u32) -> bool {
+        self.event_type == event_type && self.from_version == event_version
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> SerializationResult<SerializedEvent> {
+        let old: Old = F::decode(&event.payload)?;
+        let event_version = if self.target_type == self.event_type {
+            self.from_version + 1
+        } else {
+            1
+        };
+
+        Ok(SerializedEvent {
+            event_type: self.target_type.clone(),
+            event_version,
+            payload: F::encode(&(self.convert)(old))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serialization::{
+        Binary, EventRegistry, Format, Json, SerializationError, SerializationResult,
+        SerializedEvent,
+    };
+    use crate::test_support::{CounterEvent, Reset};
+    use crate::upcasting::{PayloadUpcaster, Upcaster, UpcasterChain};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    /// `reset` before it started recording a reason.
+    #[derive(Serialize, Deserialize)]
+    struct ResetV1 {}
+
+    fn reset_v1_to_v2(_old: ResetV1) -> Reset {
+        Reset {
+            reason: "unknown".into(),
+        }
+    }
+
+    fn registry<F: Format + 'static>() -> EventRegistry<CounterEvent, F> {
+        EventRegistry::new()
+            .register("added", 1, CounterEvent::Added)
+            .register("reset", 2, CounterEvent::Reset)
+            .upcaster(PayloadUpcaster::<F, _, _>::new("reset", 1, reset_v1_to_v2))
+            .upcaster(PayloadUpcaster::<F, (), u64>::renamed(
+                "incremented",
+                1,
+                "added",
+                |()| 1,
+            ))
+    }
+
+    fn stored<F: Format, T: Serialize>(
+        event_type: &str,
+        version: u32,
+        payload: T,
+    ) -> SerializedEvent {
+        SerializedEvent {
+            event_type: event_type.into(),
+            event_version: version,
+            payload: F::encode(&payload).unwrap(),
+        }
+    }
+
+    #[test]
+    fn old_version_is_upcasted_at_load_time() {
+        // Arrange
+        let old = stored::<Json, _>("reset", 1, ResetV1 {});
+        let expected = CounterEvent::Reset(Reset {
+            reason: "unknown".into(),
+        });
+
+        // Act
+        let result = registry::<Json>().deserialize(&old);
+
+        // Assert
+        assert_eq!(Ok(expected), result);
+    }
+
+    #[test]
+    fn upcasting_works_with_binary_payloads() {
+        let old = stored::<Binary, _>("incremented", 1, ());
+
+        let result = registry::<Binary>().deserialize(&old);
+
+        assert_eq!(Ok(CounterEvent::Added(1)), result);
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let current = stored::<Json, _>("reset", 2, json!({"reason": "audit"}));
+
+        let result = registry::<Json>().deserialize(&current);
+
+        assert_eq!(
+            Ok(CounterEvent::Reset(Reset {
+                reason: "audit".into()
+            })),
+            result
+        );
+    }
+
+    #[test]
+    fn upcasters_are_chained_until_current_version() {
+        // Arrange
+        let mut chain = UpcasterChain::new();
+        chain.push(PayloadUpcaster::<Json, Value, Value>::new(
+            "reset",
+            2,
+            |mut v| {
+                v["by"] = json!("system");
+                v
+            },
+        ));
+        chain.push(PayloadUpcaster::<Json, Value, Value>::new(
+            "reset",
+            1,
+            |_| json!({"reason": "unknown"}),
+        ));
+
+        // Act
+        let result = chain.upcast(stored::<Json, _>("reset", 1, json!({})));
+
+        // Assert
+        let expected = stored::<Json, _>("reset", 3, json!({"by": "system", "reason": "unknown"}));
+        assert_eq!(Ok(expected), result);
+    }
+
+    /// Hands the event back untouched, which would otherwise loop forever.
+    struct Stuck;
+
+    impl Upcaster for Stuck {
+        fn can_upcast(&self, event_type: &str, _event_version: u32) -> bool {
+            event_type == "reset"
+        }
+
+        fn upcast(&self, event: SerializedEvent) -> SerializationResult<SerializedEvent> {
+            Ok(event)
+        }
+    }
+
+    #[test]
+    fn upcaster_which_does_not_move_forward_is_rejected() {
+        // Arrange
+        let mut chain = UpcasterChain::new();
+        chain.push(Stuck);
+
+        // Act
+        let result = chain.upcast(stored::<Json, _>("reset", 1, json!({})));
+
+        // Assert
+        assert_eq!(
+            Err(SerializationError::Malformed(
+                "upcaster of 'reset' v1 did not move the event forward".into()
+            )),
+            result
+        );
+    }
+
+    #[test]
+    fn upcasters_going_round_in_a_cycle_are_rejected() {
+        // Arrange
+        let mut chain = UpcasterChain::new();
+        chain.push(PayloadUpcaster::<Json, Value, Value>::renamed(
+            "incremented",
+            1,
+            "increased",
+            |v| v,
+        ));
+        chain.push(PayloadUpcaster::<Json, Value, Value>::renamed(
+            "increased",
+            1,
+            "incremented",
+            |v| v,
+        ));
+
+        // Act
+        let result = chain.upcast(stored::<Json, _>("incremented", 1, json!(1)));
+
+        // Assert
+        assert_eq!(
+            Err(SerializationError::Malformed(
+                "upcaster of 'increased' v1 leads back to 'incremented' v1 in a cycle".into()
+            )),
+            result
+        );
+    }
+}
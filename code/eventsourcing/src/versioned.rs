@@ -0,0 +1,113 @@
+use crate::eventstore::Version;
+use crate::{Aggregate, AggregateId, Generation};
+use std::fmt;
+
+/// State of an aggregate kept in a `Versioned`, usually an enum of the stages the aggregate goes
+/// through, e.g. an opened and a closed bank account. The `Aggregate` derive implements it for
+/// enums.
+pub trait AggregateState: Default {
+    type Id: fmt::Display;
+
+    fn aggregate_type() -> &'static str;
+
+    /// Id of the stream the aggregate belongs to, `None` until an event told it.
+    fn id(&self) -> Option<&Self::Id>;
+}
+
+/// Aggregate made of its state and the generation kept next to it. Events replace the state as a
+/// whole when the aggregate moves to another stage, the generation is out of their reach.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Versioned<S> {
+    state: S,
+    generation: Generation,
+}
+
+impl<S> Versioned<S> {
+    /// The state as it is after the given number of events, e.g. to start a test from it.
+    pub fn new(state: S, generation: Generation) -> Versioned<S> {
+        Versioned { state, generation }
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    pub fn into_state(self) -> S {
+        self.state
+    }
+}
+
+impl<S: AggregateState> Aggregate for Versioned<S> {
+    type Id = S::Id;
+
+    fn aggregate_type() -> &'static str {
+        S::aggregate_type()
+    }
+
+    fn id(&self) -> Option<&S::Id> {
+        self.state.id()
+    }
+
+    fn version(&self) -> Version {
+        self.generation.value()
+    }
+
+    fn increment_generation(&mut self) {
+        self.generation.increment();
+    }
+}
+
+impl<S, I> AggregateId<Versioned<S>> for I
+where
+    S: AggregateState<Id = I>,
+    I: fmt::Display,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::versioned::{AggregateState, Versioned};
+    use crate::{Aggregate, Generation};
+
+    #[derive(Debug, Default, PartialEq)]
+    enum Door {
+        #[default]
+        Closed,
+        Opened(u64),
+    }
+
+    impl AggregateState for Door {
+        type Id = u64;
+
+        fn aggregate_type() -> &'static str {
+            "Door"
+        }
+
+        fn id(&self) -> Option<&u64> {
+            match self {
+                Door::Opened(id) => Some(id),
+                Door::Closed => None,
+            }
+        }
+    }
+
+    #[test]
+    fn generation_survives_replacing_the_state() {
+        // Arrange
+        let mut door = Versioned::<Door>::default();
+        door.increment_generation();
+
+        // Act
+        *door.state_mut() = Door::Opened(7);
+        door.increment_generation();
+
+        // Assert
+        assert_eq!("Door", Versioned::<Door>::aggregate_type());
+        assert_eq!((Some(&7), 2), (door.id(), door.version()));
+        assert_eq!(Versioned::new(Door::Opened(7), Generation::new(2)), door);
+    }
+}
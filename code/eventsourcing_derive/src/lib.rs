@@ -0,0 +1,499 @@
+#![crate_type = "proc-macro"]
+extern crate proc_macro;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use syn::export::{Span, TokenStream2};
+use syn::parse::{Error, Result};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DataEnum, DeriveInput, Fields, Ident, Index, Lit, LitInt,
+    LitStr, Member, Meta, MetaNameValue, NestedMeta, Type,
+};
+
+/// Implements `Event` for a struct given `#[event(type = "...", version = 1)]`, or for an enum of
+/// newtype variants by forwarding to the event each variant holds. The struct also gets
+/// `EventSchema`, the enum `EventPayload` encoding the event of the variant so it needs to
+/// implement `Serialize`.
+#[proc_macro_derive(Event, attributes(event))]
+pub fn derive_event(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    match expand_event(&ast) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Implements `AggregateEvent` for an enum of newtype variants given
+/// `#[event(aggregate = "...")]`, applying the event each variant holds. Also adds `registry()`
+/// registering the event of every variant under the type and version of its `#[event(...)]`.
+#[proc_macro_derive(AggregateEvent, attributes(event))]
+pub fn derive_aggregate_event(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    match expand_aggregate_event(&ast) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Implements `Aggregate` given `#[aggregate(type = "...", id = "...")]`. The field marked
+/// `#[aggregate(generation)]` counts the applied events and the one marked `#[aggregate(id)]` holds
+/// the id, or `#[aggregate(id = "...")]` names the field of it that does. An enum marks the id in
+/// each variant that has one and gets `AggregateState` instead, `Versioned` keeps its generation.
+#[proc_macro_derive(Aggregate, attributes(aggregate))]
+pub fn derive_aggregate(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    match expand_aggregate(&ast) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn expand_event(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let (event_type, event_version, extra) = match ast.data {
+        Data::Struct(_) => {
+            let options = get_options(&ast.attrs, "event", &["type", "version"])?;
+            let event_type = match find(&options, "type") {
+                Some(lit) => string(lit)?,
+                None => {
+                    let message = format!("missing #[event(type = \"...\")] on {}", name);
+                    return Err(Error::new_spanned(name, message));
+                }
+            };
+            let event_version = match find(&options, "version") {
+                Some(lit) => {
+                    let version = integer(lit)?;
+                    Some(quote!(#version))
+                }
+                None => None,
+            };
+            let schema_version = event_version.clone().unwrap_or_else(|| quote!(1));
+            let schema = quote! {
+                impl #impl_generics eventsourcing::serialization::EventSchema
+                    for #name #ty_generics #where_clause
+                {
+                    const EVENT_TYPE: &'static str = #event_type;
+                    const EVENT_VERSION: u32 = #schema_version;
+                }
+            };
+            (quote!(#event_type), event_version, Some(schema))
+        }
+        Data::Enum(ref data) => {
+            // the aggregate event derive reads these
+            get_options(&ast.attrs, "event", &["aggregate", "error"])?;
+            let events = newtype_variants(name, data)?;
+            let event_type = events.iter().map(
+                |event| quote!(#name::#event(ref evt) => eventsourcing::Event::event_type(evt),),
+            );
+            let event_version = events.iter().map(
+                |event| quote!(#name::#event(ref evt) => eventsourcing::Event::event_version(evt),),
+            );
+            let event_type = quote! {
+                match *self {
+                    #(#event_type)*
+                }
+            };
+            let encode_payload = events
+                .iter()
+                .map(|event| quote!(#name::#event(ref evt) => F::encode(evt),));
+            let event_version = quote! {
+                match *self {
+                    #(#event_version)*
+                }
+            };
+            let payload = quote! {
+                impl #impl_generics eventsourcing::serialization::EventPayload
+                    for #name #ty_generics #where_clause
+                {
+                    fn encode_payload<F: eventsourcing::serialization::Format>(
+                        &self,
+                    ) -> eventsourcing::serialization::SerializationResult<std::vec::Vec<u8>> {
+                        match *self {
+                            #(#encode_payload)*
+                        }
+                    }
+                }
+            };
+            (event_type, Some(event_version), Some(payload))
+        }
+        Data::Union(_) => {
+            let message = "Event can only be derived for structs and enums";
+            return Err(Error::new_spanned(name, message));
+        }
+    };
+
+    let event_version = event_version.map(|event_version| {
+        quote! {
+            fn event_version(&self) -> u32 {
+                #event_version
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics eventsourcing::Event for #name #ty_generics #where_clause {
+            fn event_type(&self) -> &'static str {
+                #event_type
+            }
+
+            #event_version
+        }
+
+        #extra
+    })
+}
+
+fn expand_aggregate_event(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let data = match ast.data {
+        Data::Enum(ref data) => data,
+        _ => {
+            let message = "AggregateEvent can only be derived for enums of newtype variants";
+            return Err(Error::new_spanned(name, message));
+        }
+    };
+    let options = get_options(&ast.attrs, "event", &["aggregate", "error"])?;
+    let aggregate: Type = match find(&options, "aggregate") {
+        Some(lit) => string(lit)?.parse()?,
+        None => {
+            let message = format!("missing #[event(aggregate = \"...\")] on {}", name);
+            return Err(Error::new_spanned(name, message));
+        }
+    };
+    let events = newtype_variants(name, data)?;
+    let error = match find(&options, "error") {
+        Some(lit) => {
+            let error: Type = string(lit)?.parse()?;
+            quote!(#error)
+        }
+        None => match data.variants.first() {
+            Some(first) => {
+                let first = &newtype_field(first.into_value())?.ty;
+                quote!(<#first as eventsourcing::AggregateEvent<#aggregate>>::Error)
+            }
+            None => {
+                let message = "an enum without variants needs #[event(error = \"...\")]";
+                return Err(Error::new_spanned(name, message));
+            }
+        },
+    };
+
+    let mut registrations = Vec::new();
+    for variant in &data.variants {
+        let event = &newtype_field(variant)?.ty;
+        let wrap = &variant.ident;
+        registrations.push(quote! {
+            .register(
+                <#event as eventsourcing::serialization::EventSchema>::EVENT_TYPE,
+                <#event as eventsourcing::serialization::EventSchema>::EVENT_VERSION,
+                #name::#wrap,
+            )
+        });
+    }
+
+    let apply = events.iter().map(|event| {
+        quote! {
+            #name::#event(evt) => {
+                eventsourcing::AggregateEvent::<#aggregate>::apply_to(evt, aggregate)
+                    .map_err(std::convert::Into::into)
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics eventsourcing::AggregateEvent<#aggregate> for #name #ty_generics #where_clause {
+            type Error = #error;
+
+            fn apply_to(self, aggregate: &mut #aggregate) -> std::result::Result<(), Self::Error> {
+                match self {
+                    #(#apply)*
+                }
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Registry decoding the event of every variant, stored under its type and version.
+            pub fn registry<F: eventsourcing::serialization::Format>(
+            ) -> eventsourcing::serialization::EventRegistry<Self, F> {
+                eventsourcing::serialization::EventRegistry::new()
+                    #(#registrations)*
+            }
+        }
+    })
+}
+
+fn expand_aggregate(ast: &DeriveInput) -> Result<TokenStream2> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let options = get_options(&ast.attrs, "aggregate", &["type", "id"])?;
+    let aggregate_type = match find(&options, "type") {
+        Some(lit) => string(lit)?,
+        None => {
+            let message = format!("missing #[aggregate(type = \"...\")] on {}", name);
+            return Err(Error::new_spanned(name, message));
+        }
+    };
+    let id_type: Type = match find(&options, "id") {
+        Some(lit) => string(lit)?.parse()?,
+        None => {
+            let message = format!("missing #[aggregate(id = \"...\")] on {}", name);
+            return Err(Error::new_spanned(name, message));
+        }
+    };
+
+    match ast.data {
+        Data::Struct(ref data) => {
+            let marked = marked_fields(&data.fields)?;
+            let (generation, span) = match marked.generation {
+                Some(generation) => generation,
+                None => {
+                    let message = format!("missing #[aggregate(generation)] field on {}", name);
+                    return Err(Error::new_spanned(name, message));
+                }
+            };
+            let id = match marked.id {
+                Some((member, Some(inner))) => quote!(Some(&self.#member.#inner)),
+                Some((member, None)) => quote!(Some(&self.#member)),
+                None => quote!(None),
+            };
+            let version = quote_spanned!(span=> eventsourcing::Generation::value(self.#generation));
+            let increment =
+                quote_spanned!(span=> eventsourcing::Generation::increment(&mut self.#generation));
+
+            Ok(quote! {
+                impl #impl_generics eventsourcing::Aggregate for #name #ty_generics #where_clause {
+                    type Id = #id_type;
+
+                    fn aggregate_type() -> &'static str {
+                        #aggregate_type
+                    }
+
+                    fn id(&self) -> std::option::Option<&Self::Id> {
+                        #id
+                    }
+
+                    fn version(&self) -> eventsourcing::eventstore::Version {
+                        #version
+                    }
+
+                    fn increment_generation(&mut self) {
+                        #increment
+                    }
+                }
+            })
+        }
+        Data::Enum(ref data) => {
+            let mut ids = Vec::new();
+            for variant in &data.variants {
+                let variant_name = &variant.ident;
+                let marked = marked_fields(&variant.fields)?;
+                if let Some((_, span)) = marked.generation {
+                    let message = "the generation of an enum aggregate is kept by \
+                                   eventsourcing::versioned::Versioned, remove \
+                                   #[aggregate(generation)]";
+                    return Err(Error::new(span, message));
+                }
+                ids.push(match marked.id {
+                    Some((member, Some(inner))) => {
+                        quote!(#name::#variant_name { #member: ref id, .. } => Some(&id.#inner),)
+                    }
+                    Some((member, None)) => {
+                        quote!(#name::#variant_name { #member: ref id, .. } => Some(id),)
+                    }
+                    None => quote!(#name::#variant_name { .. } => None,),
+                });
+            }
+
+            Ok(quote! {
+                impl #impl_generics eventsourcing::versioned::AggregateState
+                    for #name #ty_generics #where_clause
+                {
+                    type Id = #id_type;
+
+                    fn aggregate_type() -> &'static str {
+                        #aggregate_type
+                    }
+
+                    fn id(&self) -> std::option::Option<&Self::Id> {
+                        match *self { #(#ids)* }
+                    }
+                }
+            })
+        }
+        Data::Union(_) => {
+            let message = "Aggregate can only be derived for structs and enums";
+            Err(Error::new_spanned(name, message))
+        }
+    }
+}
+
+/// Fields of a struct or of a variant marked with `#[aggregate(...)]`, by name or index in a tuple.
+#[derive(Default)]
+struct MarkedFields {
+    /// Field counting the applied events, with the span of its type for pointing at a wrong one.
+    generation: Option<(Member, Span)>,
+    /// Field holding the id, or the field of it named by `#[aggregate(id = "...")]`.
+    id: Option<(Member, Option<Ident>)>,
+}
+
+fn marked_fields(fields: &Fields) -> Result<MarkedFields> {
+    let mut marked = MarkedFields::default();
+
+    for (index, field) in fields.iter().enumerate() {
+        let member = match field.ident {
+            Some(ref ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(index)),
+        };
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("aggregate"))
+        {
+            let message =
+                "expected #[aggregate(generation)], #[aggregate(id)] or #[aggregate(id = \"...\")]";
+            let marker = match attr.parse_meta()? {
+                Meta::List(ref list) if list.nested.len() == 1 => list.nested[0].clone(),
+                meta => return Err(Error::new_spanned(meta, message)),
+            };
+
+            match marker {
+                NestedMeta::Meta(Meta::Word(ref word)) if word == "generation" => {
+                    if marked.generation.is_some() {
+                        let message = "only one field can be marked #[aggregate(generation)]";
+                        return Err(Error::new_spanned(attr, message));
+                    }
+                    marked.generation = Some((member.clone(), field.ty.span()));
+                }
+                NestedMeta::Meta(Meta::Word(ref word)) if word == "id" => {
+                    set_id(&mut marked, attr, member.clone(), None)?;
+                }
+                NestedMeta::Meta(Meta::NameValue(ref name_value)) if name_value.ident == "id" => {
+                    let inner = string(&name_value.lit)?.parse()?;
+                    set_id(&mut marked, attr, member.clone(), Some(inner))?;
+                }
+                marker => return Err(Error::new_spanned(marker, message)),
+            }
+        }
+    }
+
+    Ok(marked)
+}
+
+fn set_id(
+    marked: &mut MarkedFields,
+    attr: &Attribute,
+    member: Member,
+    inner: Option<Ident>,
+) -> Result<()> {
+    if marked.id.is_some() {
+        let message = "only one field can be marked #[aggregate(id)]";
+        return Err(Error::new_spanned(attr, message));
+    }
+    marked.id = Some((member, inner));
+    Ok(())
+}
+
+/// Names of the variants, each of them has to hold a single event.
+fn newtype_variants(name: &Ident, data: &DataEnum) -> Result<Vec<Ident>> {
+    if data.variants.is_empty() {
+        let message = format!("{} has no events to dispatch to", name);
+        return Err(Error::new_spanned(name, message));
+    }
+
+    data.variants
+        .iter()
+        .map(|variant| newtype_field(variant).map(|_| variant.ident.clone()))
+        .collect()
+}
+
+fn newtype_field(variant: &syn::Variant) -> Result<&syn::Field> {
+    match variant.fields {
+        Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => Ok(&fields.unnamed[0]),
+        _ => {
+            let message = format!(
+                "expected a variant holding a single event, e.g. {0}({0})",
+                variant.ident
+            );
+            Err(Error::new_spanned(variant, message))
+        }
+    }
+}
+
+/// Values given as `#[attribute(name = value, ...)]`, only the allowed names are accepted.
+fn get_options(
+    attrs: &[Attribute],
+    attribute: &str,
+    allowed: &[&str],
+) -> Result<Vec<(Ident, Lit)>> {
+    let mut options: Vec<(Ident, Lit)> = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident(attribute)) {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            meta => {
+                let message = format!("expected #[{}(name = \"...\")]", attribute);
+                return Err(Error::new_spanned(meta, message));
+            }
+        };
+
+        for meta in nested {
+            let (name, value) = match meta {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue { ident, lit, .. })) => (ident, lit),
+                meta => {
+                    let message = "expected `name = \"...\"`";
+                    return Err(Error::new_spanned(meta, message));
+                }
+            };
+
+            if !allowed.iter().any(|allowed| name == allowed) {
+                let message = format!(
+                    "unknown {} option `{}`, expected one of: {}",
+                    attribute,
+                    name,
+                    allowed.join(", ")
+                );
+                return Err(Error::new_spanned(name, message));
+            }
+            if options.iter().any(|(known, _)| *known == name) {
+                let message = format!("duplicate {} option `{}`", attribute, name);
+                return Err(Error::new_spanned(name, message));
+            }
+            options.push((name, value));
+        }
+    }
+
+    Ok(options)
+}
+
+fn find<'a>(options: &'a [(Ident, Lit)], name: &str) -> Option<&'a Lit> {
+    options
+        .iter()
+        .find(|(known, _)| known == name)
+        .map(|(_, value)| value)
+}
+
+fn string(lit: &Lit) -> Result<&LitStr> {
+    match lit {
+        Lit::Str(lit_str) => Ok(lit_str),
+        _ => Err(Error::new_spanned(lit, "expected a string literal")),
+    }
+}
+
+fn integer(lit: &Lit) -> Result<&LitInt> {
+    match lit {
+        Lit::Int(lit_int) if lit_int.value() <= u64::from(u32::MAX) => Ok(lit_int),
+        _ => Err(Error::new_spanned(lit, "expected a version like 1")),
+    }
+}
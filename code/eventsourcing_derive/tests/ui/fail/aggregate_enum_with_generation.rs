@@ -0,0 +1,11 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Aggregate)]
+#[aggregate(type = "Door", id = "String")]
+enum Door {
+    Uninitialized,
+    Opened(#[aggregate(id)] String, #[aggregate(generation)] Generation),
+}
+
+fn main() {}
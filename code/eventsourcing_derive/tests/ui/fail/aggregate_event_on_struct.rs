@@ -0,0 +1,7 @@
+use eventsourcing_derive::AggregateEvent;
+
+#[derive(AggregateEvent)]
+#[event(aggregate = "Counter")]
+struct Added(u32);
+
+fn main() {}
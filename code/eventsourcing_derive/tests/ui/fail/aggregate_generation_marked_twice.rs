@@ -0,0 +1,13 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", id = "u64")]
+struct Counter {
+    #[aggregate(generation)]
+    generation: Generation,
+    #[aggregate(generation)]
+    previous: Generation,
+}
+
+fn main() {}
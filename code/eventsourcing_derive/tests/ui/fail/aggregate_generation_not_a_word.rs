@@ -0,0 +1,10 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Debug, Aggregate)]
+#[aggregate(type = "Door", id = "u64")]
+enum Door {
+    Opened(#[aggregate(generation = "1")] Generation),
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use eventsourcing::AggregateId;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", id = "u64")]
+struct Counter {
+    #[aggregate(generation)]
+    generation: u64,
+}
+
+impl AggregateId<Counter> for u64 {}
+
+fn main() {}
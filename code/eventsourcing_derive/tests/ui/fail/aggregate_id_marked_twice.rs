@@ -0,0 +1,17 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Debug, Aggregate)]
+#[aggregate(type = "Transfer", id = "u64")]
+enum Transfer {
+    Started {
+        #[aggregate(id)]
+        from: u64,
+        #[aggregate(id)]
+        to: u64,
+        #[aggregate(generation)]
+        generation: Generation,
+    },
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", id = "u64")]
+struct Counter {
+    #[aggregate(id)]
+    id: u64,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter")]
+struct Counter {
+    #[aggregate(generation)]
+    generation: Generation,
+}
+
+fn main() {}
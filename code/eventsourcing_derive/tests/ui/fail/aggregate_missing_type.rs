@@ -0,0 +1,8 @@
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+struct Counter {
+    value: u64,
+}
+
+fn main() {}
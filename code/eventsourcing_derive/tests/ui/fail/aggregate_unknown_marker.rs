@@ -0,0 +1,11 @@
+use eventsourcing::Generation;
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", id = "u64")]
+struct Counter {
+    #[aggregate(version)]
+    generation: Generation,
+}
+
+fn main() {}
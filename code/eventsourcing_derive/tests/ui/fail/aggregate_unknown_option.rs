@@ -0,0 +1,9 @@
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", name = "Counter")]
+struct Counter {
+    value: u64,
+}
+
+fn main() {}
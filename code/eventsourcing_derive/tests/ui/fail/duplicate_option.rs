@@ -0,0 +1,8 @@
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+#[event(type = "added")]
+#[event(type = "increased")]
+struct Added(u32);
+
+fn main() {}
@@ -0,0 +1,13 @@
+use eventsourcing_derive::{AggregateEvent, Event};
+use serde::Serialize;
+
+#[derive(Event, Serialize)]
+#[event(type = "added")]
+struct Added(u32);
+
+#[derive(Event, AggregateEvent)]
+enum CounterEvent {
+    Added(Added),
+}
+
+fn main() {}
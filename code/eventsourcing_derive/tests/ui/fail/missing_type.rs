@@ -0,0 +1,6 @@
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+struct Added(u32);
+
+fn main() {}
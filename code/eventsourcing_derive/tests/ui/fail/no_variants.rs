@@ -0,0 +1,6 @@
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+enum CounterEvent {}
+
+fn main() {}
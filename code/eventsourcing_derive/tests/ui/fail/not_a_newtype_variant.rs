@@ -0,0 +1,13 @@
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+#[event(type = "added")]
+struct Added(u32);
+
+#[derive(Event)]
+enum CounterEvent {
+    Added(Added),
+    Reset { to: u32 },
+}
+
+fn main() {}
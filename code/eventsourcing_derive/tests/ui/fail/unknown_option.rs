@@ -0,0 +1,7 @@
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+#[event(type = "added", name = "added")]
+struct Added(u32);
+
+fn main() {}
@@ -0,0 +1,55 @@
+use eventsourcing::versioned::{AggregateState, Versioned};
+use eventsourcing::{Aggregate, Generation};
+use eventsourcing_derive::Aggregate;
+
+#[derive(Debug, PartialEq)]
+struct DoorState {
+    name: String,
+}
+
+#[derive(Debug, PartialEq, Aggregate)]
+#[aggregate(type = "Door", id = "String")]
+enum Door {
+    Uninitialized,
+    Opened(#[aggregate(id = "name")] DoorState),
+    Closed {
+        #[aggregate(id)]
+        name: String,
+    },
+}
+
+impl Default for Door {
+    fn default() -> Self {
+        Door::Uninitialized
+    }
+}
+
+fn main() {
+    let front = DoorState {
+        name: "front".to_string(),
+    };
+    let mut uninitialized = Versioned::<Door>::default();
+    let mut opened = Versioned::new(Door::Opened(front), Generation::default());
+    let mut closed = Versioned::new(
+        Door::Closed {
+            name: "back".to_string(),
+        },
+        Generation::new(3),
+    );
+
+    uninitialized.increment_generation();
+    opened.increment_generation();
+    closed.increment_generation();
+
+    assert_eq!("Door", <Door as AggregateState>::aggregate_type());
+    assert_eq!("Door", Versioned::<Door>::aggregate_type());
+    assert_eq!((None, 1), (uninitialized.id(), uninitialized.version()));
+    assert_eq!(
+        (Some(&"front".to_string()), 1),
+        (opened.id(), opened.version())
+    );
+    assert_eq!(
+        (Some(&"back".to_string()), 4),
+        (closed.id(), closed.version())
+    );
+}
@@ -0,0 +1,38 @@
+use eventsourcing::{Aggregate, AggregateId, Generation};
+use eventsourcing_derive::Aggregate;
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Counter", id = "u64")]
+struct Counter {
+    #[aggregate(id)]
+    id: u64,
+    value: u64,
+    #[aggregate(generation)]
+    generation: Generation,
+}
+
+impl AggregateId<Counter> for u64 {}
+
+#[derive(Default, Aggregate)]
+#[aggregate(type = "Clock", id = "String")]
+struct Clock(u64, #[aggregate(generation)] Generation);
+
+impl AggregateId<Clock> for String {}
+
+fn main() {
+    let mut counter = Counter {
+        id: 7,
+        ..Counter::default()
+    };
+    let mut clock = Clock::default();
+
+    counter.increment_generation();
+    counter.increment_generation();
+    clock.increment_generation();
+
+    assert_eq!("Counter", Counter::aggregate_type());
+    assert_eq!("Clock", Clock::aggregate_type());
+    assert_eq!((Some(&7), 2), (counter.id(), counter.version()));
+    assert_eq!((None, 1), (clock.id(), clock.version()));
+    assert_eq!((0, 0), (counter.value, clock.0));
+}
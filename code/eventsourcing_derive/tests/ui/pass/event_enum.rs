@@ -0,0 +1,94 @@
+use eventsourcing::eventstore::Version;
+use eventsourcing::serialization::{EventPayload, Json};
+use eventsourcing::{Aggregate, AggregateEvent, AggregateId, Event};
+use eventsourcing_derive::{AggregateEvent, Event};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default)]
+struct Counter {
+    value: u32,
+}
+
+impl Aggregate for Counter {
+    type Id = u64;
+    fn aggregate_type() -> &'static str {
+        "counter"
+    }
+    fn id(&self) -> Option<&u64> {
+        None
+    }
+    fn version(&self) -> Version {
+        0
+    }
+    fn increment_generation(&mut self) {}
+}
+
+impl AggregateId<Counter> for u64 {}
+
+#[derive(Debug, PartialEq, Event, Serialize, Deserialize)]
+#[event(type = "added")]
+struct Added(u32);
+
+impl AggregateEvent<Counter> for Added {
+    type Error = String;
+    fn apply_to(self, aggregate: &mut Counter) -> Result<(), Self::Error> {
+        aggregate.value += self.0;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Event, Serialize, Deserialize)]
+#[event(type = "subtracted", version = 2)]
+struct Subtracted(u32);
+
+impl AggregateEvent<Counter> for Subtracted {
+    type Error = String;
+    fn apply_to(self, aggregate: &mut Counter) -> Result<(), Self::Error> {
+        match aggregate.value.checked_sub(self.0) {
+            Some(value) => aggregate.value = value,
+            None => return Err("below zero".to_string()),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Event, AggregateEvent)]
+#[event(aggregate = "Counter")]
+enum CounterEvent {
+    Added(Added),
+    Subtracted(Subtracted),
+}
+
+fn main() {
+    let mut counter = Counter::default();
+
+    assert_eq!("added", CounterEvent::Added(Added(1)).event_type());
+    assert_eq!(2, CounterEvent::Subtracted(Subtracted(1)).event_version());
+    assert_eq!(Ok(()), counter.apply(CounterEvent::Added(Added(3))));
+    assert_eq!(
+        Ok(()),
+        counter.apply(CounterEvent::Subtracted(Subtracted(1)))
+    );
+    assert_eq!(
+        Err("below zero".to_string()),
+        counter.apply(CounterEvent::Subtracted(Subtracted(5)))
+    );
+    assert_eq!(2, counter.value);
+    assert_eq!(
+        Ok(b"4".to_vec()),
+        CounterEvent::Added(Added(4)).encode_payload::<Json>()
+    );
+
+    let registry = CounterEvent::registry::<Json>();
+    let subtracted = registry
+        .serialize(&CounterEvent::Subtracted(Subtracted(1)))
+        .unwrap();
+    assert_eq!(
+        ("subtracted", 2),
+        (&subtracted.event_type[..], subtracted.event_version)
+    );
+    assert_eq!(
+        Ok(CounterEvent::Subtracted(Subtracted(1))),
+        registry.deserialize(&subtracted)
+    );
+}
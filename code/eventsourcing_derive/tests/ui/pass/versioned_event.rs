@@ -0,0 +1,24 @@
+use eventsourcing::Event;
+use eventsourcing_derive::Event;
+
+#[derive(Event)]
+#[event(type = "renamed", version = 2)]
+struct Renamed {
+    name: String,
+}
+
+#[derive(Event)]
+#[event(type = "removed")]
+struct Removed;
+
+fn main() {
+    let renamed = Renamed {
+        name: "counter".to_string(),
+    };
+
+    assert_eq!("renamed", renamed.event_type());
+    assert_eq!(2, renamed.event_version());
+    assert_eq!("removed", Removed.event_type());
+    assert_eq!(1, Removed.event_version());
+    assert_eq!("counter", renamed.name);
+}
@@ -0,0 +1,123 @@
+use super::types::BankAccountId;
+use super::BankAccountAggregate;
+use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::envelope::EventEnvelope;
+use eventsourcing::projection::{Projection, Rebuild};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::mem;
+use std::sync::{Arc, RwLock};
+
+/// Balance of every account derived from credited and debited money, clones share the same data.
+#[derive(Debug, Default, Clone)]
+pub struct AccountBalances {
+    balances: Arc<RwLock<HashMap<BankAccountId, u64>>>,
+}
+
+impl AccountBalances {
+    pub fn new() -> AccountBalances {
+        AccountBalances::default()
+    }
+
+    pub fn balance_of(&self, id: BankAccountId) -> Option<u64> {
+        self.balances.read().unwrap().get(&id).cloned()
+    }
+
+    /// Money held by the bank over all accounts.
+    pub fn total(&self) -> u64 {
+        self.balances.read().unwrap().values().sum()
+    }
+}
+
+impl Projection<BankAccountAggregate> for AccountBalances {
+    type Event = BankAccountEvent;
+    type Error = Infallible;
+
+    fn name(&self) -> &str {
+        "account_balances"
+    }
+
+    fn handle(&mut self, envelope: &EventEnvelope<BankAccountEvent>) -> Result<(), Infallible> {
+        let mut balances = self.balances.write().unwrap();
+
+        match envelope.event {
+            BankAccountEvent::Opened(ref evt) => {
+                balances.insert(evt.id, 0);
+            }
+            BankAccountEvent::Credited(ref evt) => {
+                *balances.entry(evt.id).or_default() += evt.amount;
+            }
+            BankAccountEvent::Debited(ref evt) => {
+                *balances.entry(evt.id).or_default() -= evt.amount;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl Rebuild<BankAccountAggregate> for AccountBalances {
+    fn fresh(&self) -> Self {
+        AccountBalances::new()
+    }
+
+    fn truncate(&mut self) -> Result<(), Infallible> {
+        self.balances.write().unwrap().clear();
+        Ok(())
+    }
+
+    fn swap(&mut self, rebuilt: Self) -> Result<(), Infallible> {
+        let rebuilt = mem::take(&mut *rebuilt.balances.write().unwrap());
+        *self.balances.write().unwrap() = rebuilt;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::prelude::{
+        AccountBalances, BankAccountRepository, DepositMoney, OpenBankAccount, WithdrawMoney,
+    };
+    use eventsourcing::eventstore::InMemoryEventStore;
+    use eventsourcing::projection::{InMemoryCheckpointStore, Projection, ProjectionRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn rebuild_replaces_stale_balances() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let repository = BankAccountRepository::new(Arc::clone(&event_store));
+        for &id in &[1, 2] {
+            repository
+                .execute(&id, OpenBankAccount::new(id, 5000))
+                .unwrap();
+            repository
+                .execute(&id, DepositMoney::new(id, 100 * id))
+                .unwrap();
+        }
+        repository.execute(&1, WithdrawMoney::new(1, 30)).unwrap();
+        let balances = AccountBalances::new();
+        let mut runner = ProjectionRunner::new(
+            event_store,
+            balances.clone(),
+            InMemoryCheckpointStore::new(),
+        );
+        // stands for the model a buggy version of the projection left behind
+        runner.catch_up().unwrap();
+        balances.balances.write().unwrap().insert(3, 999);
+        let mut reported = Vec::new();
+
+        // Act
+        let result = runner.rebuild(2, |progress| reported.push(progress.handled));
+
+        // Assert
+        assert_eq!(Ok(5), result);
+        assert_eq!(vec![2, 4, 5], reported);
+        assert_eq!(Some(70), balances.balance_of(1));
+        assert_eq!(Some(200), balances.balance_of(2));
+        assert_eq!(None, balances.balance_of(3));
+        assert_eq!(270, balances.total());
+        assert_eq!("account_balances", runner.projection().name());
+    }
+}
@@ -1,11 +1,14 @@
-use super::errors::CommandError;
+use super::errors::{BankAccountError, CommandError};
 use super::types::BankAccountId;
-use super::BankAccountAggregate;
+use super::{BankAccount, BankAccountAggregate};
 use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::eventstore::Version;
 use eventsourcing::AggregateCommand;
+use xbus_derive::XbusCommand;
 
-/// Create a new to-do item
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Closes an opened account once its balance is zero, recording why closing failed otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, XbusCommand)]
+#[xbus(result = "Version", error = "BankAccountError")]
 pub struct CloseBankAccount {
     pub id: BankAccountId,
 }
@@ -22,7 +25,7 @@ impl AggregateCommand<BankAccountAggregate> for CloseBankAccount {
     type Events = Vec<Self::Event>;
 
     fn execute_on(self, aggregate: &BankAccountAggregate) -> Result<Self::Events, Self::Error> {
-        if let BankAccountAggregate::Opened(ref data, _) = aggregate {
+        if let BankAccount::Opened(data) = aggregate.state() {
             if data.balance == 0 {
                 Ok(vec![BankAccountEvent::closed(self.id)])
             } else {
@@ -38,53 +41,33 @@ impl AggregateCommand<BankAccountAggregate> for CloseBankAccount {
 
 #[cfg(test)]
 mod tests {
-    use crate::bank::account::errors::CommandError;
     use crate::bank::account::prelude::{
         BankAccountAggregate, BankAccountEvent, BankAccountId, CloseBankAccount, CustomerId,
     };
-    use eventsourcing::Aggregate;
+    use eventsourcing::fixture::AggregateFixture;
 
     const ACCOUNT_ID: BankAccountId = 123;
     const CUSTOMER_ID: CustomerId = 5000;
 
     #[test]
     fn closing_works() {
-        assert_close(
-            vec![BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID)],
-            CloseBankAccount::new(ACCOUNT_ID),
-            Ok(vec![BankAccountEvent::closed(ACCOUNT_ID)]),
-        );
+        AggregateFixture::<BankAccountAggregate>::given(vec![BankAccountEvent::opened(
+            ACCOUNT_ID,
+            CUSTOMER_ID,
+        )])
+        .when(CloseBankAccount::new(ACCOUNT_ID))
+        .then_expect_events(vec![BankAccountEvent::closed(ACCOUNT_ID)]);
     }
 
     #[test]
     fn cant_close_account_that_has_funds() {
-        assert_close(
-            vec![
-                BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
-                BankAccountEvent::credited(ACCOUNT_ID, 20),
-            ],
-            CloseBankAccount::new(ACCOUNT_ID),
-            Ok(vec![
-                BankAccountEvent::closing_failed_due_to_funds_available(ACCOUNT_ID, 20),
-            ]),
-        );
-    }
-
-    fn assert_close(
-        intitial_events: Vec<BankAccountEvent>,
-        cmd: CloseBankAccount,
-        expected: Result<Vec<BankAccountEvent>, CommandError>,
-    ) {
-        // Arrange
-        let mut agg = BankAccountAggregate::default();
-        for event in intitial_events {
-            agg.apply(event).unwrap();
-        }
-
-        // Act
-        let result = agg.execute(cmd);
-
-        // Assert
-        assert_eq!(expected, result);
+        AggregateFixture::<BankAccountAggregate>::given(vec![
+            BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
+            BankAccountEvent::credited(ACCOUNT_ID, 20),
+        ])
+        .when(CloseBankAccount::new(ACCOUNT_ID))
+        .then_expect_events(vec![
+            BankAccountEvent::closing_failed_due_to_funds_available(ACCOUNT_ID, 20),
+        ]);
     }
 }
@@ -0,0 +1,122 @@
+use super::close_bank_account::CloseBankAccount;
+use super::deposit_money::DepositMoney;
+use super::handler::BankAccountHandler;
+use super::middleware::{validate_amounts, RetryOnConflict};
+use super::open_bank_account::OpenBankAccount;
+use super::withdraw_money::WithdrawMoney;
+use super::{BankAccountAggregate, BankAccountRepository};
+use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::eventstore::EventStore;
+use eventsourcing::snapshot::SnapshotStore;
+use std::rc::Rc;
+use std::sync::Arc;
+use xbus::CommandBus;
+
+/// Bus carrying out account commands on the accounts kept by the repository, retrying them on
+/// conflicts and rejecting invalid amounts.
+pub fn command_bus<S, P>(repository: Arc<BankAccountRepository<S, P>>) -> CommandBus
+where
+    S: EventStore<BankAccountAggregate, Event = BankAccountEvent> + 'static,
+    P: SnapshotStore<BankAccountAggregate> + 'static,
+{
+    let handler = Rc::new(BankAccountHandler::new(repository));
+
+    CommandBus::new()
+        .with_middleware(RetryOnConflict::new(3))
+        .with_middleware(validate_amounts)
+        .register::<OpenBankAccount, _>(Rc::clone(&handler))
+        .register::<DepositMoney, _>(Rc::clone(&handler))
+        .register::<WithdrawMoney, _>(Rc::clone(&handler))
+        .register::<CloseBankAccount, _>(handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::errors::{BankAccountError, CommandError};
+    use crate::bank::account::prelude::{
+        command_bus, BankAccount, BankAccountEvent, BankAccountId, BankAccountRepository,
+        CloseBankAccount, CustomerId, DepositMoney, OpenBankAccount, WithdrawMoney,
+    };
+    use eventsourcing::eventstore::{EventStore, InMemoryEventStore};
+    use eventsourcing::snapshot::{InMemorySnapshotStore, SnapshotPolicy, SnapshotStore};
+    use eventsourcing::Aggregate;
+    use std::sync::Arc;
+    use xbus::DispatchError;
+
+    const ACCOUNT_ID: BankAccountId = 123;
+    const CUSTOMER_ID: CustomerId = 5000;
+
+    #[test]
+    fn account_commands_are_dispatched_over_the_bus() {
+        // Arrange
+        let repository = Arc::new(BankAccountRepository::new(InMemoryEventStore::new()));
+        let bus = command_bus(Arc::clone(&repository));
+
+        // Act
+        let opened = bus.dispatch(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID));
+        let deposited = bus.dispatch(DepositMoney::new(ACCOUNT_ID, 50));
+        let withdrawn = bus.dispatch(WithdrawMoney::new(ACCOUNT_ID, 50));
+        let closed = bus.dispatch(CloseBankAccount::new(ACCOUNT_ID));
+
+        // Assert
+        assert_eq!(Ok(1), opened);
+        assert_eq!(Ok(2), deposited);
+        assert_eq!(Ok(3), withdrawn);
+        assert_eq!(Ok(4), closed);
+        assert_eq!(
+            BankAccountEvent::debited(ACCOUNT_ID, 50),
+            repository.event_store().load(&ACCOUNT_ID).unwrap()[2].event
+        );
+        match repository.load(&ACCOUNT_ID).unwrap().into_state() {
+            BankAccount::Closed(state) => assert_eq!(0, state.balance),
+            other => panic!("Aggregate not in Closed state: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accounts_are_snapshotted_when_commands_come_over_the_bus() {
+        // Arrange
+        let repository = Arc::new(BankAccountRepository::with_snapshots(
+            InMemoryEventStore::new(),
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Every(2),
+        ));
+        let bus = command_bus(Arc::clone(&repository));
+
+        // Act
+        bus.dispatch(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+        bus.dispatch(DepositMoney::new(ACCOUNT_ID, 50)).unwrap();
+
+        // Assert
+        let snapshot = repository
+            .snapshot_store()
+            .load_snapshot(&ACCOUNT_ID)
+            .unwrap()
+            .unwrap();
+        assert_eq!((2, 2), (snapshot.version, snapshot.aggregate.version()));
+        match snapshot.aggregate.into_state() {
+            BankAccount::Opened(state) => assert_eq!(50, state.balance),
+            other => panic!("Aggregate not in Opened state: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handler_error_is_returned_with_its_type() {
+        // Arrange
+        let bus = command_bus(Arc::new(BankAccountRepository::new(
+            InMemoryEventStore::new(),
+        )));
+
+        // Act
+        let result = bus.dispatch(DepositMoney::new(ACCOUNT_ID, 50));
+
+        // Assert
+        assert_eq!(
+            Err(DispatchError::Handler(BankAccountError::Command(
+                CommandError::NotOpened
+            ))),
+            result
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use super::types::{BankAccountId, CustomerId};
+use super::BankAccountAggregate;
+use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::envelope::EventEnvelope;
+use eventsourcing::projection::Projection;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+
+/// Open accounts of every customer, clones share the same data so one can be handed to a
+/// projection runner while the other answers queries.
+#[derive(Debug, Default, Clone)]
+pub struct CustomerAccounts {
+    data: Arc<RwLock<CustomerAccountsData>>,
+}
+
+#[derive(Debug, Default)]
+struct CustomerAccountsData {
+    accounts: HashMap<CustomerId, BTreeSet<BankAccountId>>,
+    owners: HashMap<BankAccountId, CustomerId>,
+}
+
+impl CustomerAccounts {
+    pub fn new() -> CustomerAccounts {
+        CustomerAccounts::default()
+    }
+
+    pub fn accounts_of(&self, customer_id: CustomerId) -> Vec<BankAccountId> {
+        let data = self.data.read().unwrap();
+
+        match data.accounts.get(&customer_id) {
+            Some(accounts) => accounts.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Projection<BankAccountAggregate> for CustomerAccounts {
+    type Event = BankAccountEvent;
+    type Error = Infallible;
+
+    fn name(&self) -> &str {
+        "customer_accounts"
+    }
+
+    fn handle(&mut self, envelope: &EventEnvelope<BankAccountEvent>) -> Result<(), Infallible> {
+        let mut data = self.data.write().unwrap();
+
+        match envelope.event {
+            BankAccountEvent::Opened(ref evt) => {
+                data.owners.insert(evt.id, evt.customer_id);
+                data.accounts
+                    .entry(evt.customer_id)
+                    .or_default()
+                    .insert(evt.id);
+            }
+            BankAccountEvent::Closed(ref evt) => {
+                if let Some(customer_id) = data.owners.remove(&evt.id) {
+                    if let Some(accounts) = data.accounts.get_mut(&customer_id) {
+                        accounts.remove(&evt.id);
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::prelude::{
+        BankAccountRepository, CloseBankAccount, CustomerAccounts, OpenBankAccount,
+    };
+    use eventsourcing::eventstore::InMemoryEventStore;
+    use eventsourcing::projection::{InMemoryCheckpointStore, ProjectionRunner};
+    use std::sync::Arc;
+
+    #[test]
+    fn lists_open_accounts_of_a_customer() {
+        // Arrange
+        let event_store = Arc::new(InMemoryEventStore::new());
+        let repository = BankAccountRepository::new(Arc::clone(&event_store));
+        for &(id, customer_id) in &[(1, 5000), (2, 5000), (3, 5000), (4, 6000)] {
+            repository
+                .execute(&id, OpenBankAccount::new(id, customer_id))
+                .unwrap();
+        }
+        repository.execute(&2, CloseBankAccount::new(2)).unwrap();
+        let customer_accounts = CustomerAccounts::new();
+        let mut runner = ProjectionRunner::new(
+            event_store,
+            customer_accounts.clone(),
+            InMemoryCheckpointStore::new(),
+        );
+
+        // Act
+        runner.catch_up().unwrap();
+
+        // Assert
+        assert_eq!(vec![1, 3], customer_accounts.accounts_of(5000));
+        assert_eq!(vec![4], customer_accounts.accounts_of(6000));
+        assert_eq!(Vec::<u64>::new(), customer_accounts.accounts_of(7000));
+    }
+}
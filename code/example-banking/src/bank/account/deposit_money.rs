@@ -1,11 +1,14 @@
-use super::errors::CommandError;
+use super::errors::{BankAccountError, CommandError};
 use super::types::BankAccountId;
-use super::BankAccountAggregate;
+use super::{BankAccount, BankAccountAggregate};
 use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::eventstore::Version;
 use eventsourcing::AggregateCommand;
+use xbus_derive::XbusCommand;
 
-/// Create a new to-do item
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Credits the amount to an opened account.
+#[derive(Debug, Clone, PartialEq, Eq, XbusCommand)]
+#[xbus(result = "Version", error = "BankAccountError")]
 pub struct DepositMoney {
     pub id: BankAccountId,
     pub amount: u64,
@@ -23,7 +26,7 @@ impl AggregateCommand<BankAccountAggregate> for DepositMoney {
     type Events = Vec<Self::Event>;
 
     fn execute_on(self, aggregate: &BankAccountAggregate) -> Result<Self::Events, Self::Error> {
-        if let BankAccountAggregate::Opened(ref _data, _) = aggregate {
+        if let BankAccount::Opened(_data) = aggregate.state() {
             let events = vec![BankAccountEvent::credited(self.id, self.amount)];
             Ok(events)
         } else {
@@ -34,39 +37,110 @@ impl AggregateCommand<BankAccountAggregate> for DepositMoney {
 
 #[cfg(test)]
 mod tests {
-    use crate::bank::account::errors::CommandError;
     use crate::bank::account::prelude::{
-        BankAccountAggregate, BankAccountEvent, BankAccountId, CustomerId, DepositMoney,
+        BankAccount, BankAccountAggregate, BankAccountEvent, BankAccountId, BankAccountRepository,
+        CustomerId, DepositMoney, OpenBankAccount,
     };
+    use eventsourcing::eventstore::{FileEventStore, InMemoryEventStore};
+    use eventsourcing::fixture::AggregateFixture;
+    use eventsourcing::serialization::Json;
+    use eventsourcing::snapshot::{InMemorySnapshotStore, SnapshotPolicy, SnapshotStore};
     use eventsourcing::Aggregate;
+    use std::fs::{self, OpenOptions};
+    use std::path::Path;
+    use tempfile::TempDir;
 
     const ACCOUNT_ID: BankAccountId = 123;
     const CUSTOMER_ID: CustomerId = 5000;
 
     #[test]
     fn depositing_money_works() {
-        assert_deposit(
-            vec![BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID)],
-            DepositMoney::new(ACCOUNT_ID, 49),
-            Ok(vec![BankAccountEvent::credited(ACCOUNT_ID, 49)]),
-        );
+        AggregateFixture::<BankAccountAggregate>::given(vec![BankAccountEvent::opened(
+            ACCOUNT_ID,
+            CUSTOMER_ID,
+        )])
+        .when(DepositMoney::new(ACCOUNT_ID, 49))
+        .then_expect_events(vec![BankAccountEvent::credited(ACCOUNT_ID, 49)])
+        .then_inspect_state(|account| match account.state() {
+            BankAccount::Opened(state) => {
+                assert_eq!((49, 2), (state.balance, account.version()))
+            }
+            other => panic!("Aggregate not in Opened state: {:?}", other),
+        });
+    }
+
+    #[test]
+    fn balance_survives_crash_between_two_deposits() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let repository = open_repository(dir.path());
+        repository
+            .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+        repository
+            .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 49))
+            .unwrap();
+        let segment = repository.event_store().path().to_owned();
+        let intact_len = fs::metadata(&segment).unwrap().len();
+        repository
+            .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 51))
+            .unwrap();
+        drop(repository);
+        // killed while the second deposit was only half way on disk
+        let file = OpenOptions::new().write(true).open(&segment).unwrap();
+        file.set_len(intact_len + 3).unwrap();
+
+        // Act
+        let repository = open_repository(dir.path());
+        let version = repository.execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 1));
+
+        // Assert
+        assert_eq!(3, version.unwrap());
+        match repository.load(&ACCOUNT_ID).unwrap().into_state() {
+            BankAccount::Opened(state) => assert_eq!(50, state.balance),
+            other => panic!("Aggregate not in Opened state: {:?}", other),
+        }
     }
 
-    fn assert_deposit(
-        intitial_events: Vec<BankAccountEvent>,
-        cmd: DepositMoney,
-        expected: Result<Vec<BankAccountEvent>, CommandError>,
-    ) {
-        //Arrange
-        let mut agg = BankAccountAggregate::default();
-        for event in intitial_events {
-            agg.apply(event).unwrap();
+    #[test]
+    fn balance_is_restored_from_snapshot_and_tail() {
+        // Arrange
+        let repository = BankAccountRepository::with_snapshots(
+            InMemoryEventStore::new(),
+            InMemorySnapshotStore::new(),
+            SnapshotPolicy::Every(3),
+        );
+        repository
+            .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+        for amount in 1..=4 {
+            repository
+                .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, amount))
+                .unwrap();
         }
 
         // Act
-        let result = agg.execute(cmd);
+        let result = repository.load(&ACCOUNT_ID).unwrap();
 
         // Assert
-        assert_eq!(expected, result);
+        let snapshot = repository
+            .snapshot_store()
+            .load_snapshot(&ACCOUNT_ID)
+            .unwrap()
+            .unwrap();
+        assert_eq!(3, snapshot.version);
+        assert_eq!((3, 5), (snapshot.aggregate.version(), result.version()));
+        match (snapshot.aggregate.into_state(), result.into_state()) {
+            (BankAccount::Opened(snapshot), BankAccount::Opened(state)) => {
+                assert_eq!((3, 10), (snapshot.balance, state.balance));
+            }
+            other => panic!("Aggregate not in Opened state: {:?}", other),
+        }
+    }
+
+    fn open_repository(
+        dir: &Path,
+    ) -> BankAccountRepository<FileEventStore<BankAccountAggregate, BankAccountEvent, Json>> {
+        BankAccountRepository::new(FileEventStore::open(dir, BankAccountEvent::registry()).unwrap())
     }
 }
@@ -1,3 +1,5 @@
+use eventsourcing::eventstore::{EventStoreError, VersionConflict};
+use eventsourcing::repository::ExecuteError;
 use std::error;
 use std::fmt;
 
@@ -7,21 +9,16 @@ pub enum CommandError {
     NotOpened,
 }
 
-impl error::Error for CommandError {
-    fn description(&self) -> &str {
-        match *self {
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
             CommandError::NotOpened => "attempt to execute command on account that is not opened",
             CommandError::AlreadyCreated => "attempt to create when already created",
-        }
+        })
     }
 }
 
-impl fmt::Display for CommandError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let err: &error::Error = self;
-        f.write_str(err.description())
-    }
-}
+impl error::Error for CommandError {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EventError {
@@ -30,19 +27,62 @@ pub enum EventError {
     NotOpened,
 }
 
-impl error::Error for EventError {
-    fn description(&self) -> &str {
-        match *self {
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
             EventError::NotInitialized => "attempt to execute event before creation",
             EventError::AlreadyOpened => "attempt to open when already opened",
             EventError::NotOpened => "attempt to closed when not opened",
+        })
+    }
+}
+
+impl error::Error for EventError {}
+
+/// Why a command sent to a bank account was not carried out, whichever store keeps the account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BankAccountError {
+    Command(CommandError),
+    Event(EventError),
+    /// Somebody else changed the account in the meantime.
+    Conflict(VersionConflict),
+    /// The event or snapshot store failed with the given message.
+    Store(String),
+}
+
+impl<S, P> From<ExecuteError<CommandError, EventError, S, P>> for BankAccountError
+where
+    S: fmt::Display,
+    P: fmt::Display,
+{
+    fn from(err: ExecuteError<CommandError, EventError, S, P>) -> Self {
+        match err {
+            ExecuteError::Command(err) => BankAccountError::Command(err),
+            ExecuteError::Event(err) => BankAccountError::Event(err),
+            ExecuteError::Store(EventStoreError::Conflict(conflict)) => {
+                BankAccountError::Conflict(conflict)
+            }
+            ExecuteError::Store(EventStoreError::Store(err)) => {
+                BankAccountError::Store(err.to_string())
+            }
+            ExecuteError::Snapshot(err) => BankAccountError::Store(err.to_string()),
         }
     }
 }
 
-impl fmt::Display for EventError {
+impl fmt::Display for BankAccountError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let err: &error::Error = self;
-        f.write_str(err.description())
+        match self {
+            BankAccountError::Command(err) => err.fmt(f),
+            BankAccountError::Event(err) => write!(f, "can not apply event: {}", err),
+            BankAccountError::Conflict(conflict) => write!(
+                f,
+                "account moved on, expected version {} but found {}",
+                conflict.expected, conflict.actual
+            ),
+            BankAccountError::Store(err) => write!(f, "event store failed: {}", err),
+        }
     }
 }
+
+impl error::Error for BankAccountError {}
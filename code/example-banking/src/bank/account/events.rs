@@ -1,9 +1,13 @@
 use super::types::*;
-use super::{BankAccountAggregate, BankAccountState};
+use super::{BankAccount, BankAccountAggregate, BankAccountState};
 use crate::bank::account::errors::EventError;
-use eventsourcing::{AggregateEvent, Event};
+use eventsourcing::AggregateEvent;
+use eventsourcing_derive::{AggregateEvent, Event};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Stored as the bare payload of the variant, the registry picks the variant by event type.
+#[derive(Debug, PartialEq, Eq, Clone, Event, AggregateEvent)]
+#[event(aggregate = "BankAccountAggregate", error = "EventError")]
 pub enum BankAccountEvent {
     Opened(Opened),
     Credited(Credited),
@@ -48,53 +52,19 @@ impl BankAccountEvent {
     }
 }
 
-impl Event for BankAccountEvent {
-    fn event_type(&self) -> &'static str {
-        match *self {
-            BankAccountEvent::Opened(ref evt) => evt.event_type(),
-            BankAccountEvent::Credited(ref evt) => evt.event_type(),
-            BankAccountEvent::Debited(ref evt) => evt.event_type(),
-            BankAccountEvent::NotEnoughFunds(ref evt) => evt.event_type(),
-            BankAccountEvent::Closed(ref evt) => evt.event_type(),
-            BankAccountEvent::ClosingFailedDueToFundsAvailable(ref evt) => evt.event_type(),
-        }
-    }
-}
-
-impl AggregateEvent<BankAccountAggregate> for BankAccountEvent {
-    type Error = EventError;
-    fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        match self {
-            BankAccountEvent::Opened(evt) => evt.apply_to(aggregate),
-            BankAccountEvent::Credited(evt) => evt.apply_to(aggregate),
-            BankAccountEvent::Debited(evt) => evt.apply_to(aggregate),
-            BankAccountEvent::NotEnoughFunds(evt) => evt.apply_to(aggregate),
-            BankAccountEvent::Closed(evt) => evt.apply_to(aggregate),
-            BankAccountEvent::ClosingFailedDueToFundsAvailable(evt) => evt.apply_to(aggregate),
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "opened", version = 1)]
 pub struct Opened {
     pub id: BankAccountId,
     pub customer_id: CustomerId,
 }
 
-impl Event for Opened {
-    fn event_type(&self) -> &'static str {
-        "opened"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for Opened {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if BankAccountAggregate::Uninitialized == *aggregate {
-            *aggregate = BankAccountAggregate::Opened(
-                BankAccountState::new(self.id, self.customer_id),
-                Vec::new(),
-            );
+        if BankAccount::Uninitialized == *aggregate.state() {
+            *aggregate.state_mut() =
+                BankAccount::Opened(BankAccountState::new(self.id, self.customer_id));
             Ok(())
         } else {
             Err(EventError::AlreadyOpened)
@@ -102,22 +72,17 @@ impl AggregateEvent<BankAccountAggregate> for Opened {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "credited", version = 1)]
 pub struct Credited {
     pub id: BankAccountId,
     pub amount: u64,
 }
 
-impl Event for Credited {
-    fn event_type(&self) -> &'static str {
-        "credited"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for Credited {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if let BankAccountAggregate::Opened(ref mut data, _) = aggregate {
+        if let BankAccount::Opened(data) = aggregate.state_mut() {
             data.balance += self.amount;
             Ok(())
         } else {
@@ -126,22 +91,17 @@ impl AggregateEvent<BankAccountAggregate> for Credited {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "debited", version = 1)]
 pub struct Debited {
     pub id: BankAccountId,
     pub amount: u64,
 }
 
-impl Event for Debited {
-    fn event_type(&self) -> &'static str {
-        "debited"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for Debited {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if let BankAccountAggregate::Opened(ref mut data, _) = aggregate {
+        if let BankAccount::Opened(data) = aggregate.state_mut() {
             data.balance -= self.amount;
             Ok(())
         } else {
@@ -150,23 +110,18 @@ impl AggregateEvent<BankAccountAggregate> for Debited {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "not_enough_funds", version = 1)]
 pub struct NotEnoughFunds {
     pub id: BankAccountId,
     pub amount: u64,
     pub current_balance: u64,
 }
 
-impl Event for NotEnoughFunds {
-    fn event_type(&self) -> &'static str {
-        "not_enough_funds"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for NotEnoughFunds {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if let BankAccountAggregate::Opened(_, _) = aggregate {
+        if let BankAccount::Opened(..) = aggregate.state() {
             Ok(())
         } else {
             Err(EventError::NotInitialized)
@@ -174,22 +129,18 @@ impl AggregateEvent<BankAccountAggregate> for NotEnoughFunds {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "closed", version = 1)]
 pub struct Closed {
     pub id: BankAccountId,
 }
 
-impl Event for Closed {
-    fn event_type(&self) -> &'static str {
-        "closed"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for Closed {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if let BankAccountAggregate::Opened(ref data, ref x) = aggregate {
-            *aggregate = BankAccountAggregate::Closed(data.to_owned(), x.to_owned());
+        if let BankAccount::Opened(data) = aggregate.state() {
+            let closed = BankAccount::Closed(data.to_owned());
+            *aggregate.state_mut() = closed;
             Ok(())
         } else {
             Err(EventError::AlreadyOpened)
@@ -197,22 +148,17 @@ impl AggregateEvent<BankAccountAggregate> for Closed {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Event)]
+#[event(type = "closing_failed_due_to_funds_available", version = 1)]
 pub struct ClosingFailedDueToFundsAvailable {
     pub id: BankAccountId,
     pub current_balance: u64,
 }
 
-impl Event for ClosingFailedDueToFundsAvailable {
-    fn event_type(&self) -> &'static str {
-        "closing_failed_due_to_funds_available"
-    }
-}
-
 impl AggregateEvent<BankAccountAggregate> for ClosingFailedDueToFundsAvailable {
     type Error = EventError;
     fn apply_to(self, aggregate: &mut BankAccountAggregate) -> Result<(), Self::Error> {
-        if let BankAccountAggregate::Opened(_, _) = aggregate {
+        if let BankAccount::Opened(..) = aggregate.state() {
             Ok(())
         } else {
             Err(EventError::NotOpened)
@@ -224,8 +170,9 @@ impl AggregateEvent<BankAccountAggregate> for ClosingFailedDueToFundsAvailable {
 mod tests {
     use crate::bank::account::errors::EventError;
     use crate::bank::account::prelude::{
-        BankAccountAggregate, BankAccountEvent, BankAccountId, CustomerId,
+        BankAccount, BankAccountAggregate, BankAccountEvent, BankAccountId, CustomerId,
     };
+    use eventsourcing::serialization::{Binary, Json};
     use eventsourcing::Aggregate;
     const ACCOUNT_ID: BankAccountId = 123;
     const CUSTOMER_ID: CustomerId = 5000;
@@ -240,7 +187,8 @@ mod tests {
         agg.apply(event).unwrap();
 
         // Assert
-        if let BankAccountAggregate::Opened(state, _) = agg {
+        assert_eq!((Some(&ACCOUNT_ID), 1), (agg.id(), agg.version()));
+        if let BankAccount::Opened(state) = agg.into_state() {
             assert_eq!(ACCOUNT_ID, state.id);
             assert_eq!(CUSTOMER_ID, state.customer_id);
             assert_eq!(0, state.balance);
@@ -278,7 +226,7 @@ mod tests {
         agg.apply(event).unwrap();
 
         // Assert
-        if let BankAccountAggregate::Opened(state, _) = agg {
+        if let BankAccount::Opened(state) = agg.into_state() {
             assert_eq!(expected_balance, state.balance);
         } else {
             panic!("Aggregate not in Opened state");
@@ -303,7 +251,7 @@ mod tests {
         }
 
         // Assert
-        if let BankAccountAggregate::Opened(state, _) = agg {
+        if let BankAccount::Opened(state) = agg.into_state() {
             assert_eq!(expected_balance, state.balance);
         } else {
             panic!("Aggregate not in Opened state");
@@ -323,7 +271,7 @@ mod tests {
         agg.apply(event).unwrap();
 
         // Assert
-        if let BankAccountAggregate::Opened(state, _) = agg {
+        if let BankAccount::Opened(state) = agg.into_state() {
             assert_eq!(expected_balance, state.balance);
         } else {
             panic!("Aggregate not in Opened state");
@@ -343,7 +291,8 @@ mod tests {
         agg.apply(event).unwrap();
 
         // Assert
-        if let BankAccountAggregate::Closed(state, _) = agg {
+        assert_eq!((Some(&ACCOUNT_ID), 2), (agg.id(), agg.version()));
+        if let BankAccount::Closed(state) = agg.into_state() {
             assert_eq!(expected_balance, state.balance);
         } else {
             panic!("Aggregate not in Closed state");
@@ -368,10 +317,47 @@ mod tests {
         }
 
         // Assert
-        if let BankAccountAggregate::Opened(state, _) = agg {
+        if let BankAccount::Opened(state) = agg.into_state() {
             assert_eq!(expected_balance, state.balance);
         } else {
             panic!("Aggregate not in Opened state");
         }
     }
+
+    #[test]
+    fn credited_is_serialized_to_json() {
+        // Arrange
+        let expected =
+            r#"{"event_type":"credited","event_version":1,"payload":{"id":123,"amount":49}}"#;
+
+        // Act
+        let bytes = BankAccountEvent::registry::<Json>()
+            .to_bytes(&BankAccountEvent::credited(ACCOUNT_ID, 49))
+            .unwrap();
+
+        // Assert
+        assert_eq!(expected, String::from_utf8(bytes).unwrap());
+    }
+
+    #[test]
+    fn all_events_survive_serialization() {
+        let events = vec![
+            BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
+            BankAccountEvent::credited(ACCOUNT_ID, 49),
+            BankAccountEvent::debited(ACCOUNT_ID, 48),
+            BankAccountEvent::not_enough_funds(ACCOUNT_ID, 49, 1),
+            BankAccountEvent::closing_failed_due_to_funds_available(ACCOUNT_ID, 1),
+            BankAccountEvent::closed(ACCOUNT_ID),
+        ];
+        let json = BankAccountEvent::registry::<Json>();
+        let binary = BankAccountEvent::registry::<Binary>();
+
+        for event in events {
+            let from_json = json.from_bytes(&json.to_bytes(&event).unwrap());
+            let from_binary = binary.from_bytes(&binary.to_bytes(&event).unwrap());
+
+            assert_eq!(Ok(event.clone()), from_json);
+            assert_eq!(Ok(event), from_binary);
+        }
+    }
 }
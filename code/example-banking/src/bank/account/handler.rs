@@ -0,0 +1,124 @@
+use super::close_bank_account::CloseBankAccount;
+use super::deposit_money::DepositMoney;
+use super::open_bank_account::OpenBankAccount;
+use super::withdraw_money::WithdrawMoney;
+use super::{BankAccountAggregate, BankAccountRepository};
+use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::eventstore::EventStore;
+use eventsourcing::repository::ExecuteResult;
+use eventsourcing::snapshot::{NoSnapshots, SnapshotStore};
+use std::sync::Arc;
+use xbus_derive::XbusCommandHandler;
+
+/// Carries out every account command on the account it names, kept by the repository.
+#[derive(XbusCommandHandler)]
+#[handles = "OpenBankAccount"]
+#[handles = "DepositMoney"]
+#[handles = "WithdrawMoney"]
+#[handles = "CloseBankAccount"]
+#[xbus(bound = "S: 'static, P: 'static")]
+pub struct BankAccountHandler<S, P = NoSnapshots>
+where
+    S: EventStore<BankAccountAggregate, Event = BankAccountEvent>,
+    P: SnapshotStore<BankAccountAggregate>,
+{
+    repository: Arc<BankAccountRepository<S, P>>,
+}
+
+impl<S, P> BankAccountHandler<S, P>
+where
+    S: EventStore<BankAccountAggregate, Event = BankAccountEvent>,
+    P: SnapshotStore<BankAccountAggregate>,
+{
+    pub fn new(repository: Arc<BankAccountRepository<S, P>>) -> BankAccountHandler<S, P> {
+        BankAccountHandler { repository }
+    }
+
+    pub fn handle_open_bank_account(
+        &self,
+        cmd: OpenBankAccount,
+    ) -> ExecuteResult<BankAccountAggregate, S, OpenBankAccount, P> {
+        let id = cmd.id;
+        self.repository.execute(&id, cmd)
+    }
+
+    pub fn handle_deposit_money(
+        &self,
+        cmd: DepositMoney,
+    ) -> ExecuteResult<BankAccountAggregate, S, DepositMoney, P> {
+        let id = cmd.id;
+        self.repository.execute(&id, cmd)
+    }
+
+    pub fn handle_withdraw_money(
+        &self,
+        cmd: WithdrawMoney,
+    ) -> ExecuteResult<BankAccountAggregate, S, WithdrawMoney, P> {
+        let id = cmd.id;
+        self.repository.execute(&id, cmd)
+    }
+
+    pub fn handle_close_bank_account(
+        &self,
+        cmd: CloseBankAccount,
+    ) -> ExecuteResult<BankAccountAggregate, S, CloseBankAccount, P> {
+        let id = cmd.id;
+        self.repository.execute(&id, cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::errors::CommandError;
+    use crate::bank::account::prelude::{
+        BankAccountEvent, BankAccountHandler, BankAccountId, BankAccountRepository, CustomerId,
+        OpenBankAccount,
+    };
+    use eventsourcing::eventstore::{EventStore, InMemoryEventStore};
+    use eventsourcing::repository::ExecuteError;
+    use std::sync::Arc;
+
+    const ACCOUNT_ID: BankAccountId = 123;
+    const CUSTOMER_ID: CustomerId = 5000;
+
+    #[test]
+    fn open_bank_account_handler_stores_opened_event() {
+        // Arrange
+        let repository = Arc::new(BankAccountRepository::new(InMemoryEventStore::new()));
+        let handler = BankAccountHandler::new(Arc::clone(&repository));
+
+        // Act
+        let result =
+            handler.handle_open_bank_account(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID));
+
+        // Assert
+        let stored = repository.event_store().load(&ACCOUNT_ID).unwrap();
+        assert_eq!(Ok(1), result);
+        assert_eq!(1, stored.len());
+        assert_eq!(
+            BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
+            stored[0].event
+        );
+        assert_eq!("BankAccount", stored[0].aggregate_type);
+    }
+
+    #[test]
+    fn open_bank_account_handler_refuses_to_open_twice() {
+        // Arrange
+        let repository = Arc::new(BankAccountRepository::new(InMemoryEventStore::new()));
+        let handler = BankAccountHandler::new(repository);
+        handler
+            .handle_open_bank_account(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+
+        // Act
+        let result =
+            handler.handle_open_bank_account(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID));
+
+        // Assert
+        assert_eq!(
+            Err(ExecuteError::Command(CommandError::AlreadyCreated)),
+            result
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use super::deposit_money::DepositMoney;
+use super::errors::BankAccountError;
+use super::withdraw_money::WithdrawMoney;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use xbus::{DispatchError, Dispatched, Middleware, Outcome};
+
+/// Records every command with how it turned out and how long it took, clones share the records.
+/// Wrap a bus in it with `CommandBus::wrapped_in` to see the commands other middleware of the bus
+/// rejected or retried as well.
+#[derive(Debug, Default, Clone)]
+pub struct CommandLog {
+    entries: Arc<Mutex<Vec<String>>>,
+}
+
+impl CommandLog {
+    pub fn new() -> CommandLog {
+        CommandLog::default()
+    }
+
+    pub fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+impl Middleware for CommandLog {
+    fn handle(&self, command: &Dispatched, next: &dyn Fn() -> Outcome) -> Outcome {
+        let started = Instant::now();
+        let outcome = next();
+        let status = match outcome {
+            Ok(_) => "done".to_string(),
+            Err(DispatchError::Handler(ref err)) => match err.downcast_ref::<BankAccountError>() {
+                Some(err) => format!("failed: {}", err),
+                None => "failed".to_string(),
+            },
+            Err(DispatchError::Rejected(ref reason)) => format!("rejected: {}", reason),
+            Err(DispatchError::NoHandler(_)) => "has no handler".to_string(),
+        };
+        self.entries.lock().unwrap().push(format!(
+            "{} {} in {:?}",
+            short_name(command.name()),
+            status,
+            started.elapsed()
+        ));
+        outcome
+    }
+}
+
+/// Rejects moving no money at all before it gets recorded on the account.
+pub fn validate_amounts(command: &Dispatched, next: &dyn Fn() -> Outcome) -> Outcome {
+    let amount = command
+        .downcast_ref::<DepositMoney>()
+        .map(|cmd| cmd.amount)
+        .or_else(|| {
+            command
+                .downcast_ref::<WithdrawMoney>()
+                .map(|cmd| cmd.amount)
+        });
+
+    match amount {
+        Some(0) => Err(DispatchError::Rejected("amount must not be zero".into())),
+        _ => next(),
+    }
+}
+
+/// Carries the command out again on the account's new state when somebody else changed it in the
+/// meantime, giving up after the given number of attempts.
+pub struct RetryOnConflict {
+    attempts: usize,
+}
+
+impl RetryOnConflict {
+    pub fn new(attempts: usize) -> RetryOnConflict {
+        RetryOnConflict {
+            attempts: attempts.max(1),
+        }
+    }
+}
+
+impl Middleware for RetryOnConflict {
+    fn handle(&self, _command: &Dispatched, next: &dyn Fn() -> Outcome) -> Outcome {
+        let mut outcome = next();
+        for _ in 1..self.attempts {
+            if !is_conflict(&outcome) {
+                break;
+            }
+            outcome = next();
+        }
+        outcome
+    }
+}
+
+fn is_conflict(outcome: &Outcome) -> bool {
+    match outcome {
+        Err(DispatchError::Handler(err)) => {
+            matches!(err.downcast_ref(), Some(BankAccountError::Conflict(_)))
+        }
+        _ => false,
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandLog, RetryOnConflict};
+    use crate::bank::account::errors::BankAccountError;
+    use crate::bank::account::prelude::{
+        command_bus, BankAccountId, BankAccountRepository, CustomerId, DepositMoney,
+        OpenBankAccount, WithdrawMoney,
+    };
+    use eventsourcing::eventstore::{InMemoryEventStore, Version, VersionConflict};
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use xbus::{CommandBus, DispatchError, XbusCommandHandler};
+
+    const ACCOUNT_ID: BankAccountId = 123;
+    const CUSTOMER_ID: CustomerId = 5000;
+
+    #[test]
+    fn zero_amounts_are_rejected_and_every_command_is_logged() {
+        // Arrange
+        let log = CommandLog::new();
+        let repository = Arc::new(BankAccountRepository::new(InMemoryEventStore::new()));
+        let bus = command_bus(repository).wrapped_in(log.clone());
+        bus.dispatch(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+
+        // Act
+        let deposited = bus.dispatch(DepositMoney::new(ACCOUNT_ID, 0));
+        let withdrawn = bus.dispatch(WithdrawMoney::new(ACCOUNT_ID + 1, 10));
+
+        // Assert
+        assert_eq!(
+            Err(DispatchError::Rejected("amount must not be zero".into())),
+            deposited
+        );
+        assert!(withdrawn.is_err());
+        // the log wraps the validation, so it sees the rejected deposit as well
+        let entries = log.entries();
+        assert_eq!(3, entries.len());
+        assert!(entries[0].starts_with("OpenBankAccount done in "));
+        assert!(entries[1].starts_with("DepositMoney rejected: amount must not be zero in "));
+        assert!(entries[2].starts_with(
+            "WithdrawMoney failed: attempt to execute command on account that is not opened in "
+        ));
+    }
+
+    #[test]
+    fn conflicting_command_is_retried() {
+        // Arrange
+        let bus = |attempts| {
+            CommandBus::new()
+                .with_middleware(RetryOnConflict::new(attempts))
+                .register(ConflictingHandler {
+                    conflicts: Cell::new(2),
+                })
+        };
+
+        // Act
+        let given_up = bus(2).dispatch(DepositMoney::new(ACCOUNT_ID, 10));
+        let retried = bus(3).dispatch(DepositMoney::new(ACCOUNT_ID, 10));
+
+        // Assert
+        assert_eq!(
+            Err(DispatchError::Handler(BankAccountError::Conflict(
+                VersionConflict {
+                    expected: 2,
+                    actual: 3,
+                }
+            ))),
+            given_up
+        );
+        assert_eq!(Ok(3), retried);
+    }
+
+    /// Stands for a handler whose account gets changed by somebody else the given number of times.
+    struct ConflictingHandler {
+        conflicts: Cell<u32>,
+    }
+
+    impl XbusCommandHandler<DepositMoney> for ConflictingHandler {
+        fn handle(&self, _command: DepositMoney) -> Result<Version, BankAccountError> {
+            if self.conflicts.get() > 0 {
+                self.conflicts.set(self.conflicts.get() - 1);
+                let conflict = VersionConflict {
+                    expected: 2,
+                    actual: 3,
+                };
+                return Err(BankAccountError::Conflict(conflict));
+            }
+            Ok(3)
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::envelope::EventEnvelope;
+use std::sync::{Arc, Mutex};
+use xbus::{EventBus, XbusEventListener};
+
+/// Lets customers know a withdrawal got refused, clones share the sent notifications.
+#[derive(Debug, Default, Clone)]
+pub struct NotEnoughFundsNotifier {
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl NotEnoughFundsNotifier {
+    pub fn new() -> NotEnoughFundsNotifier {
+        NotEnoughFundsNotifier::default()
+    }
+
+    pub fn sent(&self) -> Vec<String> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl XbusEventListener<EventEnvelope<BankAccountEvent>> for NotEnoughFundsNotifier {
+    fn handle(&self, envelope: &EventEnvelope<BankAccountEvent>) {
+        if let BankAccountEvent::NotEnoughFunds(ref evt) = envelope.event {
+            self.sent.lock().unwrap().push(format!(
+                "Account {} can not pay out {}, its balance is {}",
+                evt.id, evt.amount, evt.current_balance
+            ));
+        }
+    }
+}
+
+/// Bus handing committed account events over to the listeners.
+pub fn event_bus(notifier: NotEnoughFundsNotifier) -> EventBus<EventEnvelope<BankAccountEvent>> {
+    EventBus::new().subscribe_to_event_type("not_enough_funds", notifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::account::prelude::{
+        event_bus, BankAccountId, BankAccountRepository, CustomerId, DepositMoney,
+        NotEnoughFundsNotifier, OpenBankAccount, WithdrawMoney,
+    };
+    use eventsourcing::eventstore::InMemoryEventStore;
+
+    const ACCOUNT_ID: BankAccountId = 123;
+    const CUSTOMER_ID: CustomerId = 5000;
+
+    #[test]
+    fn customer_is_notified_about_refused_withdrawal() {
+        // Arrange
+        let notifier = NotEnoughFundsNotifier::new();
+        let repository = BankAccountRepository::new(InMemoryEventStore::new())
+            .with_publisher(event_bus(notifier.clone()));
+        repository
+            .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+        repository
+            .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 49))
+            .unwrap();
+
+        // Act
+        repository
+            .execute(&ACCOUNT_ID, WithdrawMoney::new(ACCOUNT_ID, 20))
+            .unwrap();
+        repository
+            .execute(&ACCOUNT_ID, WithdrawMoney::new(ACCOUNT_ID, 30))
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            vec!["Account 123 can not pay out 30, its balance is 29".to_string()],
+            notifier.sent()
+        );
+    }
+}
@@ -1,11 +1,16 @@
+pub use super::balances::AccountBalances;
 pub use super::close_bank_account::CloseBankAccount;
+pub use super::command_bus::command_bus;
+pub use super::customer_accounts::CustomerAccounts;
 pub use super::deposit_money::DepositMoney;
 pub use super::events::BankAccountEvent;
-pub use super::open_bank_account::BankAccountRepository;
+pub use super::handler::BankAccountHandler;
+pub use super::middleware::CommandLog;
+pub use super::notifications::{event_bus, NotEnoughFundsNotifier};
 pub use super::open_bank_account::OpenBankAccount;
-pub use super::open_bank_account::OpenBankAccountHandler;
 pub use super::types::BankAccountId;
 pub use super::types::CustomerId;
 pub use super::withdraw_money::WithdrawMoney;
+pub use super::BankAccount;
 pub use super::BankAccountAggregate;
-pub use super::BankAccountState;
+pub use super::BankAccountRepository;
@@ -1,11 +1,15 @@
-use super::errors::CommandError;
+use super::errors::{BankAccountError, CommandError};
 use super::types::BankAccountId;
-use super::BankAccountAggregate;
+use super::{BankAccount, BankAccountAggregate};
 use crate::bank::account::events::BankAccountEvent;
+use eventsourcing::eventstore::Version;
 use eventsourcing::AggregateCommand;
+use xbus_derive::XbusCommand;
 
-/// Create a new to-do item
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Debits the amount from an opened account, recording not enough funds instead when the
+/// balance does not cover it.
+#[derive(Debug, Clone, PartialEq, Eq, XbusCommand)]
+#[xbus(result = "Version", error = "BankAccountError")]
 pub struct WithdrawMoney {
     pub id: BankAccountId,
     pub amount: u64,
@@ -23,7 +27,7 @@ impl AggregateCommand<BankAccountAggregate> for WithdrawMoney {
     type Events = Vec<Self::Event>;
 
     fn execute_on(self, aggregate: &BankAccountAggregate) -> Result<Self::Events, Self::Error> {
-        if let BankAccountAggregate::Opened(ref data, _) = aggregate {
+        if let BankAccount::Opened(data) = aggregate.state() {
             if data.balance >= self.amount {
                 Ok(vec![BankAccountEvent::debited(self.id, self.amount)])
             } else {
@@ -41,55 +45,31 @@ impl AggregateCommand<BankAccountAggregate> for WithdrawMoney {
 
 #[cfg(test)]
 mod tests {
-    use crate::bank::account::errors::CommandError;
     use crate::bank::account::prelude::{
         BankAccountAggregate, BankAccountEvent, BankAccountId, CustomerId, WithdrawMoney,
     };
-    use eventsourcing::Aggregate;
+    use eventsourcing::fixture::AggregateFixture;
 
     const ACCOUNT_ID: BankAccountId = 123;
     const CUSTOMER_ID: CustomerId = 5000;
 
     #[test]
     fn withdrawing_money_works() {
-        assert_withdraw(
-            vec![
-                BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
-                BankAccountEvent::credited(ACCOUNT_ID, 50),
-            ],
-            WithdrawMoney::new(ACCOUNT_ID, 49),
-            Ok(vec![BankAccountEvent::debited(ACCOUNT_ID, 49)]),
-        );
+        AggregateFixture::<BankAccountAggregate>::given(vec![
+            BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
+            BankAccountEvent::credited(ACCOUNT_ID, 50),
+        ])
+        .when(WithdrawMoney::new(ACCOUNT_ID, 49))
+        .then_expect_events(vec![BankAccountEvent::debited(ACCOUNT_ID, 49)]);
     }
 
     #[test]
     fn not_enough_funds() {
-        assert_withdraw(
-            vec![
-                BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
-                BankAccountEvent::credited(ACCOUNT_ID, 48),
-            ],
-            WithdrawMoney::new(ACCOUNT_ID, 49),
-            Ok(vec![BankAccountEvent::not_enough_funds(ACCOUNT_ID, 49, 48)]),
-        );
+        AggregateFixture::<BankAccountAggregate>::given(vec![
+            BankAccountEvent::opened(ACCOUNT_ID, CUSTOMER_ID),
+            BankAccountEvent::credited(ACCOUNT_ID, 48),
+        ])
+        .when(WithdrawMoney::new(ACCOUNT_ID, 49))
+        .then_expect_events(vec![BankAccountEvent::not_enough_funds(ACCOUNT_ID, 49, 48)]);
     }
-
-    fn assert_withdraw(
-        intitial_events: Vec<BankAccountEvent>,
-        cmd: WithdrawMoney,
-        expected: Result<Vec<BankAccountEvent>, CommandError>,
-    ) {
-        // Arrange
-        let mut agg = BankAccountAggregate::default();
-        for event in intitial_events {
-            agg.apply(event).unwrap();
-        }
-
-        // Act
-        let result = agg.execute(cmd);
-
-        // Assert
-        assert_eq!(expected, result);
-    }
-
 }
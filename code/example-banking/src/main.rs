@@ -1,8 +1,12 @@
 mod bank;
 
 use crate::bank::account::prelude::*;
-use eventsourcing::eventstore::DummyEventStore;
+use eventsourcing::eventstore::InMemoryEventStore;
+use eventsourcing::projection::{InMemoryCheckpointStore, ProjectionRunner};
+use eventsourcing::serialization::Json;
+use eventsourcing::tracked::Tracked;
 use eventsourcing::Aggregate;
+use std::sync::Arc;
 
 fn main() {
     open_bank_account_example1();
@@ -11,6 +15,13 @@ fn main() {
     withdraw_example();
     not_enough_funds_example();
     close_example();
+    serialization_example();
+    customer_accounts_example();
+    account_balances_rebuild_example();
+    command_bus_example();
+    event_bus_example();
+    #[cfg(feature = "sqlite")]
+    sqlite_example();
     println!("Done!");
 }
 
@@ -20,31 +31,31 @@ const CUSTOMER_ID: CustomerId = 123;
 fn open_bank_account_example1() {
     // Arrange
     let cmd = OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID);
-    let _event_store = DummyEventStore {};
-    let repository = BankAccountRepository {};
-    let handler = OpenBankAccountHandler::new(repository);
+    let event_store = InMemoryEventStore::new();
+    let repository = Arc::new(BankAccountRepository::new(event_store));
+    let handler = BankAccountHandler::new(repository);
 
     // Act
-    let result = handler.handle(cmd);
+    let result = handler.handle_open_bank_account(cmd);
 
     // Arrange
-    assert_eq!(Ok(()), result);
+    assert_eq!(Ok(1), result);
 }
 
 fn open_bank_account_example2() {
     // Arrange
-    let mut agg = BankAccountAggregate::default();
+    let mut agg = Tracked::<BankAccountAggregate, BankAccountEvent>::default();
     let cmd = OpenBankAccount::new(123, 5000);
 
     // Act
-    let events = agg.execute(cmd).unwrap();
-
-    for event in events {
-        agg.apply(event).unwrap();
-    }
+    agg.execute(cmd).unwrap();
 
     // Assert
-    if let BankAccountAggregate::Opened(state, _) = agg {
+    assert_eq!(
+        vec![BankAccountEvent::opened(123, 5000)],
+        agg.take_uncommitted()
+    );
+    if let BankAccount::Opened(state) = agg.into_inner().into_state() {
         assert_eq!(123, state.id);
         assert_eq!(5000, state.customer_id);
         assert_eq!(0, state.balance);
@@ -68,7 +79,7 @@ fn deposit_example() {
     }
 
     // Assert
-    if let BankAccountAggregate::Opened(state, _) = agg {
+    if let BankAccount::Opened(state) = agg.into_state() {
         assert_eq!(expected_balance, state.balance);
     } else {
         panic!("Aggregate not in Opened state");
@@ -91,7 +102,7 @@ fn withdraw_example() {
     }
 
     // Assert
-    if let BankAccountAggregate::Opened(state, _) = agg {
+    if let BankAccount::Opened(state) = agg.into_state() {
         assert_eq!(expected_balance, state.balance);
     } else {
         panic!("Aggregate not in Opened state");
@@ -114,7 +125,7 @@ fn not_enough_funds_example() {
 
     // Assert
 
-    if let BankAccountAggregate::Opened(state, _) = agg {
+    if let BankAccount::Opened(state) = agg.into_state() {
         assert_eq!(expected_balance, state.balance);
     } else {
         panic!("Aggregate not in Opened state");
@@ -136,9 +147,153 @@ fn close_example() {
     }
 
     // Assert
-    if let BankAccountAggregate::Closed(state, _) = agg {
+    if let BankAccount::Closed(state) = agg.into_state() {
         assert_eq!(expected_balance, state.balance);
     } else {
         panic!("Aggregate not in Closed state");
     }
 }
+
+fn serialization_example() {
+    // Arrange
+    let registry = BankAccountEvent::registry::<Json>();
+    let event = BankAccountEvent::credited(123, 49);
+
+    // Act
+    let bytes = registry.to_bytes(&event).unwrap();
+    println!("{}", String::from_utf8_lossy(&bytes));
+
+    // Assert
+    assert_eq!(Ok(event), registry.from_bytes(&bytes));
+}
+
+fn customer_accounts_example() {
+    // Arrange
+    let event_store = Arc::new(InMemoryEventStore::new());
+    let repository = BankAccountRepository::new(Arc::clone(&event_store));
+    let customer_accounts = CustomerAccounts::new();
+    let mut runner = ProjectionRunner::new(
+        event_store,
+        customer_accounts.clone(),
+        InMemoryCheckpointStore::new(),
+    );
+
+    // Act
+    for id in 1..=3 {
+        repository
+            .execute(&id, OpenBankAccount::new(id, CUSTOMER_ID))
+            .unwrap();
+    }
+    runner.catch_up().unwrap();
+
+    // Assert
+    assert_eq!(vec![1, 2, 3], customer_accounts.accounts_of(CUSTOMER_ID));
+}
+
+fn account_balances_rebuild_example() {
+    // Arrange
+    let event_store = Arc::new(InMemoryEventStore::new());
+    let repository = BankAccountRepository::new(Arc::clone(&event_store));
+    repository
+        .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+        .unwrap();
+    repository
+        .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 49))
+        .unwrap();
+    let balances = AccountBalances::new();
+    let mut runner = ProjectionRunner::new(
+        event_store,
+        balances.clone(),
+        InMemoryCheckpointStore::new(),
+    );
+
+    // Act
+    let handled = runner
+        .rebuild(100, |progress| {
+            println!(
+                "rebuilt {} events up to position {}",
+                progress.handled, progress.position
+            )
+        })
+        .unwrap();
+
+    // Assert
+    assert_eq!(2, handled);
+    assert_eq!(Some(49), balances.balance_of(ACCOUNT_ID));
+    assert_eq!(49, balances.total());
+}
+
+fn command_bus_example() {
+    // Arrange
+    let repository = Arc::new(BankAccountRepository::new(InMemoryEventStore::new()));
+    let log = CommandLog::new();
+    let bus = command_bus(repository).wrapped_in(log.clone());
+
+    // Act
+    let opened = bus.dispatch(OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID));
+    let deposited = bus.dispatch(DepositMoney::new(ACCOUNT_ID, 49));
+    let withdrawn = bus.dispatch(WithdrawMoney::new(ACCOUNT_ID, 50));
+    let unknown = bus.dispatch(DepositMoney::new(ACCOUNT_ID + 1, 49));
+
+    // Assert
+    assert_eq!(Ok(1), opened);
+    assert_eq!(Ok(2), deposited);
+    // not enough funds is recorded as an event, it is not an error
+    assert_eq!(Ok(3), withdrawn);
+    match unknown {
+        Err(err) => println!("{}", err),
+        other => panic!("Deposit to unknown account went through: {:?}", other),
+    }
+    for entry in log.entries() {
+        println!("{}", entry);
+    }
+}
+
+fn event_bus_example() {
+    // Arrange
+    let notifier = NotEnoughFundsNotifier::new();
+    let repository = BankAccountRepository::new(InMemoryEventStore::new())
+        .with_publisher(event_bus(notifier.clone()));
+    repository
+        .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+        .unwrap();
+
+    // Act
+    repository
+        .execute(&ACCOUNT_ID, WithdrawMoney::new(ACCOUNT_ID, 50))
+        .unwrap();
+
+    // Assert
+    for notification in notifier.sent() {
+        println!("{}", notification);
+    }
+}
+
+/// Deposits into the same account on every run, the balance keeps growing across restarts.
+#[cfg(feature = "sqlite")]
+fn sqlite_example() {
+    use eventsourcing::eventstore::SqliteEventStore;
+
+    // Arrange
+    let path = std::env::var("BANK_DATABASE").unwrap_or_else(|_| "bank.sqlite".into());
+    let event_store = SqliteEventStore::open(&path, BankAccountEvent::registry::<Json>()).unwrap();
+    let repository = BankAccountRepository::new(event_store);
+
+    if let BankAccount::Uninitialized = repository.load(&ACCOUNT_ID).unwrap().into_state() {
+        repository
+            .execute(&ACCOUNT_ID, OpenBankAccount::new(ACCOUNT_ID, CUSTOMER_ID))
+            .unwrap();
+    }
+
+    // Act
+    repository
+        .execute(&ACCOUNT_ID, DepositMoney::new(ACCOUNT_ID, 10))
+        .unwrap();
+
+    // Assert
+    if let BankAccount::Opened(state) = repository.load(&ACCOUNT_ID).unwrap().into_state() {
+        println!("Balance stored in {}: {}", path, state.balance);
+    } else {
+        panic!("Aggregate not in Opened state");
+    }
+}
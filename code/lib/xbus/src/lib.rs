@@ -1,9 +1,562 @@
-extern crate actix;
+#[cfg(feature = "actor")]
+pub extern crate actix;
+#[cfg(feature = "actor")]
 extern crate futures;
-use actix::*;
+#[cfg(feature = "actor")]
+use actix::{Actor, Context, Handler};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::{error, fmt};
 
-pub struct CommandBus;
+#[cfg(feature = "actor")]
+pub use actix::Message;
 
+/// Stands in for `actix::Message` when the bus is not an actor, so commands declare their result
+/// the same way with or without the `actor` feature.
+#[cfg(not(feature = "actor"))]
+pub trait Message {
+    type Result: 'static;
+}
+
+/// Command dispatched over the bus to the single handler registered for its type.
+pub trait XbusCommand: 'static {
+    /// What the handler returns when it carries the command out, e.g. the new stream version.
+    type Result: 'static;
+    /// Why the handler refused or failed to carry the command out.
+    type Error: 'static;
+}
+
+pub trait XbusCommandHandler<C: XbusCommand>: 'static {
+    fn handle(&self, command: C) -> Result<C::Result, C::Error>;
+}
+
+/// Lets one handler be registered for all the commands it handles, sharing its state.
+impl<C, H> XbusCommandHandler<C> for Rc<H>
+where
+    C: XbusCommand,
+    H: XbusCommandHandler<C>,
+{
+    fn handle(&self, command: C) -> Result<C::Result, C::Error> {
+        (**self).handle(command)
+    }
+}
+
+/// Same as for `Rc`, for handlers shared with other threads as well.
+impl<C, H> XbusCommandHandler<C> for Arc<H>
+where
+    C: XbusCommand,
+    H: XbusCommandHandler<C>,
+{
+    fn handle(&self, command: C) -> Result<C::Result, C::Error> {
+        (**self).handle(command)
+    }
+}
+
+pub type DispatchResult<C> =
+    Result<<C as XbusCommand>::Result, DispatchError<<C as XbusCommand>::Error>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchError<E> {
+    /// Nothing got registered to handle commands of the given type.
+    NoHandler(&'static str),
+    /// A middleware refused to pass the command on, e.g. because it is invalid or not allowed.
+    Rejected(String),
+    Handler(E),
+}
+
+impl<E: fmt::Display> fmt::Display for DispatchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DispatchError::NoHandler(command) => write!(f, "no handler for {}", command),
+            DispatchError::Rejected(reason) => write!(f, "command rejected: {}", reason),
+            DispatchError::Handler(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for DispatchError<E> {}
+
+/// Result or error of the command's own type with the type erased, as middleware sees it.
+pub type Outcome = Result<Box<dyn Any>, DispatchError<Box<dyn Any>>>;
+
+/// Command on its way through the middleware to its handler.
+pub struct Dispatched<'a> {
+    name: &'static str,
+    command: &'a dyn Any,
+}
+
+impl<'a> Dispatched<'a> {
+    /// Type name of the command, e.g. `example_banking::DepositMoney`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn downcast_ref<C: XbusCommand>(&self) -> Option<&C> {
+        self.command.downcast_ref()
+    }
+}
+
+/// Wraps every dispatched command, e.g. to log, time, validate, authorize or retry it.
+///
+/// Calling `next` passes the command on to the next middleware and finally to the handler, so a
+/// middleware may call it several times or not at all to short-circuit the command.
+pub trait Middleware: 'static {
+    fn handle(&self, command: &Dispatched, next: &dyn Fn() -> Outcome) -> Outcome;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&Dispatched, &dyn Fn() -> Outcome) -> Outcome + 'static,
+{
+    fn handle(&self, command: &Dispatched, next: &dyn Fn() -> Outcome) -> Outcome {
+        self(command, next)
+    }
+}
+
+/// Routes every command through the middleware to the handler registered for its type, either
+/// called directly or, with the `actor` feature, started as an actor commands are sent to.
+#[derive(Default)]
+pub struct CommandBus {
+    /// `Box<dyn XbusCommandHandler<C>>` of every registered command type `C`.
+    handlers: HashMap<TypeId, Box<dyn Any>>,
+    /// Outermost first.
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl CommandBus {
+    pub fn new() -> CommandBus {
+        CommandBus {
+            handlers: HashMap::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Lets the handler carry out commands of type `C`, replacing the one registered before.
+    pub fn register<C, H>(mut self, handler: H) -> CommandBus
+    where
+        C: XbusCommand,
+        H: XbusCommandHandler<C>,
+    {
+        let handler: Box<dyn XbusCommandHandler<C>> = Box::new(handler);
+        self.handlers.insert(TypeId::of::<C>(), Box::new(handler));
+        self
+    }
+
+    /// Wraps all commands in the middleware, inside of the middleware added before.
+    pub fn with_middleware<M: Middleware>(mut self, middleware: M) -> CommandBus {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Wraps all commands in the middleware, outside of the middleware added before, e.g. to see
+    /// commands the others rejected or retried on a bus somebody else set up.
+    pub fn wrapped_in<M: Middleware>(mut self, middleware: M) -> CommandBus {
+        self.middleware.insert(0, Box::new(middleware));
+        self
+    }
+
+    /// Every call of the handler gets its own clone of the command, so middleware can retry it.
+    pub fn dispatch<C: XbusCommand + Clone>(&self, command: C) -> DispatchResult<C> {
+        let name = type_name::<C>();
+        let handler = self
+            .handlers
+            .get(&TypeId::of::<C>())
+            .and_then(|handler| handler.downcast_ref::<Box<dyn XbusCommandHandler<C>>>());
+        let handle = || -> Outcome {
+            let handler = handler.ok_or(DispatchError::NoHandler(name))?;
+            match handler.handle(command.clone()) {
+                Ok(result) => Ok(Box::new(result)),
+                Err(err) => Err(DispatchError::Handler(Box::new(err))),
+            }
+        };
+        let dispatched = Dispatched {
+            name,
+            command: &command,
+        };
+
+        match self.run(&self.middleware, &dispatched, &handle) {
+            Ok(result) => Ok(*result
+                .downcast()
+                .unwrap_or_else(|_| panic!("middleware changed the result type of {}", name))),
+            Err(DispatchError::Handler(err)) => Err(DispatchError::Handler(
+                *err.downcast()
+                    .unwrap_or_else(|_| panic!("middleware changed the error type of {}", name)),
+            )),
+            Err(DispatchError::NoHandler(command)) => Err(DispatchError::NoHandler(command)),
+            Err(DispatchError::Rejected(reason)) => Err(DispatchError::Rejected(reason)),
+        }
+    }
+
+    fn run(
+        &self,
+        middleware: &[Box<dyn Middleware>],
+        command: &Dispatched,
+        handle: &dyn Fn() -> Outcome,
+    ) -> Outcome {
+        match middleware.split_first() {
+            Some((outer, inner)) => outer.handle(command, &|| self.run(inner, command, handle)),
+            None => handle(),
+        }
+    }
+}
+
+#[cfg(feature = "actor")]
 impl Actor for CommandBus {
     type Context = Context<Self>;
 }
+
+#[cfg(feature = "actor")]
+impl<C> Handler<C> for CommandBus
+where
+    C: XbusCommand + Clone + Message<Result = DispatchResult<C>>,
+{
+    type Result = DispatchResult<C>;
+
+    fn handle(&mut self, command: C, _ctx: &mut Context<Self>) -> Self::Result {
+        self.dispatch(command)
+    }
+}
+
+/// Event published over the bus to every listener of its event type or aggregate type.
+pub trait XbusEvent: 'static {
+    fn event_type(&self) -> &str;
+    fn aggregate_type(&self) -> &str;
+}
+
+pub trait XbusEventListener<E: XbusEvent>: Send + Sync + 'static {
+    fn handle(&self, event: &E);
+}
+
+impl<E, F> XbusEventListener<E> for F
+where
+    E: XbusEvent,
+    F: Fn(&E) + Send + Sync + 'static,
+{
+    fn handle(&self, event: &E) {
+        self(event)
+    }
+}
+
+/// What a listener got subscribed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topic {
+    /// Events of a single type, e.g. `not_enough_funds`.
+    EventType(String),
+    /// All events of an aggregate type, e.g. `BankAccount`.
+    AggregateType(String),
+}
+
+impl Topic {
+    pub fn matches<E: XbusEvent>(&self, event: &E) -> bool {
+        match self {
+            Topic::EventType(event_type) => event_type == event.event_type(),
+            Topic::AggregateType(aggregate_type) => aggregate_type == event.aggregate_type(),
+        }
+    }
+}
+
+/// Hands every published event to all listeners subscribed to it, in the order they got
+/// subscribed.
+pub struct EventBus<E> {
+    listeners: Vec<(Topic, Box<dyn XbusEventListener<E>>)>,
+}
+
+impl<E: XbusEvent> EventBus<E> {
+    pub fn new() -> EventBus<E> {
+        EventBus {
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn subscribe<L>(mut self, topic: Topic, listener: L) -> EventBus<E>
+    where
+        L: XbusEventListener<E>,
+    {
+        self.listeners.push((topic, Box::new(listener)));
+        self
+    }
+
+    pub fn subscribe_to_event_type<L>(self, event_type: &str, listener: L) -> EventBus<E>
+    where
+        L: XbusEventListener<E>,
+    {
+        self.subscribe(Topic::EventType(event_type.to_owned()), listener)
+    }
+
+    pub fn subscribe_to_aggregate_type<L>(self, aggregate_type: &str, listener: L) -> EventBus<E>
+    where
+        L: XbusEventListener<E>,
+    {
+        self.subscribe(Topic::AggregateType(aggregate_type.to_owned()), listener)
+    }
+
+    pub fn publish(&self, event: &E) {
+        for (topic, listener) in &self.listeners {
+            if topic.matches(event) {
+                listener.handle(event);
+            }
+        }
+    }
+}
+
+impl<E: XbusEvent> Default for EventBus<E> {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CommandBus, DispatchError, DispatchResult, Dispatched, EventBus, Message, Outcome,
+        XbusCommand, XbusCommandHandler, XbusEvent,
+    };
+    #[cfg(feature = "actor")]
+    use actix::{Actor, System};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct Add(u32);
+
+    impl XbusCommand for Add {
+        type Result = u32;
+        type Error = String;
+    }
+
+    impl Message for Add {
+        type Result = DispatchResult<Add>;
+    }
+
+    /// Adds up everything it is given, refusing to go over the limit.
+    struct AddHandler {
+        total: Cell<u32>,
+        limit: u32,
+    }
+
+    impl XbusCommandHandler<Add> for AddHandler {
+        fn handle(&self, command: Add) -> Result<u32, String> {
+            let total = self.total.get() + command.0;
+            if total > self.limit {
+                return Err(format!("{} is over the limit", total));
+            }
+            self.total.set(total);
+            Ok(total)
+        }
+    }
+
+    fn bus() -> CommandBus {
+        CommandBus::new().register(AddHandler {
+            total: Cell::new(0),
+            limit: 10,
+        })
+    }
+
+    #[test]
+    fn command_is_dispatched_to_registered_handler() {
+        // Arrange
+        let bus = bus();
+
+        // Act
+        let first = bus.dispatch(Add(3));
+        let second = bus.dispatch(Add(4));
+        let over = bus.dispatch(Add(5));
+
+        // Assert
+        assert_eq!(Ok(3), first);
+        assert_eq!(Ok(7), second);
+        assert_eq!(
+            Err(DispatchError::Handler("12 is over the limit".into())),
+            over
+        );
+    }
+
+    #[test]
+    fn shared_handler_keeps_its_state_across_registrations() {
+        // Arrange
+        let handler = Rc::new(AddHandler {
+            total: Cell::new(0),
+            limit: 10,
+        });
+        let bus = CommandBus::new().register::<Add, _>(Rc::clone(&handler));
+
+        // Act
+        let result = bus.dispatch(Add(3));
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert_eq!(3, handler.total.get());
+    }
+
+    #[test]
+    fn command_without_handler_is_rejected() {
+        let result = CommandBus::new().dispatch(Add(3));
+
+        assert_eq!(Err(DispatchError::NoHandler("xbus::tests::Add")), result);
+    }
+
+    #[cfg(feature = "actor")]
+    #[test]
+    fn command_is_sent_to_started_bus() {
+        // Arrange
+        let mut system = System::new("test");
+        let bus = bus().start();
+
+        // Act
+        let result = system.block_on(bus.send(Add(3))).unwrap();
+
+        // Assert
+        assert_eq!(Ok(3), result);
+    }
+
+    #[test]
+    fn middleware_wraps_handler_outermost_first() {
+        // Arrange
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let layer = |name: &'static str| {
+            let log = Rc::clone(&log);
+            move |command: &Dispatched, next: &dyn Fn() -> Outcome| {
+                let amount = command.downcast_ref::<Add>().unwrap().0;
+                log.borrow_mut().push(format!("{} got {}", name, amount));
+                let outcome = next();
+                let total = outcome
+                    .as_ref()
+                    .ok()
+                    .and_then(|total| total.downcast_ref::<u32>());
+                log.borrow_mut().push(format!("{} saw {:?}", name, total));
+                outcome
+            }
+        };
+        let bus = bus()
+            .with_middleware(layer("outer"))
+            .with_middleware(layer("inner"))
+            .wrapped_in(layer("outermost"));
+
+        // Act
+        let result = bus.dispatch(Add(3));
+
+        // Assert
+        assert_eq!(Ok(3), result);
+        assert_eq!(
+            vec![
+                "outermost got 3",
+                "outer got 3",
+                "inner got 3",
+                "inner saw Some(3)",
+                "outer saw Some(3)",
+                "outermost saw Some(3)"
+            ],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn middleware_can_reject_command() {
+        // Arrange
+        let bus =
+            bus().with_middleware(
+                |command: &Dispatched, next: &dyn Fn() -> Outcome| match command
+                    .downcast_ref::<Add>()
+                {
+                    Some(Add(0)) => Err(DispatchError::Rejected("nothing to add".into())),
+                    _ => next(),
+                },
+            );
+
+        // Act
+        let rejected = bus.dispatch(Add(0));
+        let added = bus.dispatch(Add(2));
+
+        // Assert
+        assert_eq!(
+            Err(DispatchError::Rejected("nothing to add".into())),
+            rejected
+        );
+        assert_eq!(Ok(2), added);
+    }
+
+    #[test]
+    fn middleware_can_retry_failed_command() {
+        // Arrange
+        let bus = CommandBus::new()
+            .register(FlakyHandler {
+                failures: Cell::new(2),
+            })
+            .with_middleware(|_: &Dispatched, next: &dyn Fn() -> Outcome| {
+                let mut outcome = next();
+                for _ in 0..2 {
+                    if outcome.is_ok() {
+                        break;
+                    }
+                    outcome = next();
+                }
+                outcome
+            });
+
+        // Act
+        let result = bus.dispatch(Add(3));
+
+        // Assert
+        assert_eq!(Ok(3), result);
+    }
+
+    /// Fails the given number of times before it gets anything done.
+    struct FlakyHandler {
+        failures: Cell<u32>,
+    }
+
+    impl XbusCommandHandler<Add> for FlakyHandler {
+        fn handle(&self, command: Add) -> Result<u32, String> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err("try again".into());
+            }
+            Ok(command.0)
+        }
+    }
+
+    /// Stands for `(aggregate type, event type)` of a committed event.
+    struct Happened(&'static str, &'static str);
+
+    impl XbusEvent for Happened {
+        fn event_type(&self) -> &str {
+            self.1
+        }
+
+        fn aggregate_type(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[test]
+    fn event_is_published_to_listeners_of_its_type_and_aggregate_type() {
+        // Arrange
+        let heard = Arc::new(Mutex::new(Vec::new()));
+        let listener = |name: &'static str| {
+            let heard = Arc::clone(&heard);
+            move |event: &Happened| heard.lock().unwrap().push((name, event.1))
+        };
+        let bus = EventBus::new()
+            .subscribe_to_aggregate_type("BankAccount", listener("accounts"))
+            .subscribe_to_event_type("not_enough_funds", listener("notifications"))
+            .subscribe_to_event_type("opened", listener("customers"));
+
+        // Act
+        bus.publish(&Happened("BankAccount", "credited"));
+        bus.publish(&Happened("BankAccount", "not_enough_funds"));
+        bus.publish(&Happened("Customer", "registered"));
+
+        // Assert
+        assert_eq!(
+            vec![
+                ("accounts", "credited"),
+                ("accounts", "not_enough_funds"),
+                ("notifications", "not_enough_funds"),
+            ],
+            *heard.lock().unwrap()
+        );
+    }
+}
@@ -5,63 +5,240 @@ extern crate quote;
 extern crate syn;
 
 use proc_macro::TokenStream;
-use syn::export::Span;
+use syn::export::{Span, TokenStream2};
 use syn::parse::{Error, Result};
-use syn::{parse_macro_input, Attribute, DeriveInput, Ident, Lit, Meta, MetaNameValue};
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, Attribute, DeriveInput, Ident, Lit, LitStr, Meta, MetaNameValue, NestedMeta,
+    Path, Token, Type, WherePredicate,
+};
 
-#[proc_macro_derive(XbusCommandHandler, attributes(handles))]
+/// Implements `XbusCommandHandler` for every command named by a `#[handles = "..."]` attribute,
+/// forwarding to the handler's own `handle` method. A handler of several commands gets one
+/// method per command instead, e.g. `handle_deposit_money` for `#[handles = "DepositMoney"]`.
+#[proc_macro_derive(XbusCommandHandler, attributes(handles, xbus))]
 pub fn add_handle(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
+    match expand_handler(ast) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn expand_handler(ast: DeriveInput) -> Result<TokenStream2> {
+    let options = get_options(&ast.attrs, &["result", "error", "bound"])?;
+    let commands = get_commands(&ast.attrs, &ast.ident)?;
     let handler_name = ast.ident;
-    let command_name: Ident = get_command(&ast.attrs[0]).unwrap();
+    let mut generics = ast.generics;
+    if let Some(bound) = options.bound {
+        generics.make_where_clause().predicates.extend(bound);
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let result = type_or_inferred(options.result);
+    let error = type_or_inferred(options.error);
 
-    let expanded = quote! {
-        impl Handler<#command_name> for xbus::CommandBus {
-            type Result = Result<(), ()>;
+    let single = commands.len() == 1;
+    let impls = commands.iter().map(|command_name| {
+        let method = if single {
+            Ident::new("handle", Span::call_site())
+        } else {
+            method_for(command_name)
+        };
 
-            fn handle(&mut self, command: #command_name, _ctx: &mut Context<Self>) -> Self::Result {
-                #handler_name::handle(command)
+        quote! {
+            impl #impl_generics xbus::XbusCommandHandler<#command_name> for #handler_name #ty_generics #where_clause {
+                fn handle(
+                    &self,
+                    command: #command_name,
+                ) -> std::result::Result<
+                    <#command_name as xbus::XbusCommand>::Result,
+                    <#command_name as xbus::XbusCommand>::Error,
+                > {
+                    let result: std::result::Result<#result, #error> = #handler_name::#method(self, command);
+                    result
+                        .map(std::convert::Into::into)
+                        .map_err(std::convert::Into::into)
+                }
             }
         }
-    };
-    return TokenStream::from(expanded);
+    });
+
+    Ok(quote! { #(#impls)* })
 }
 
-fn get_command(attr: &Attribute) -> Result<Ident> {
-    let meta = match attr.interpret_meta() {
-        Some(meta) => meta,
-        None => return Err(Error::new(Span::call_site(), "An error...")),
-    };
+/// Commands named by all `#[handles = "..."]` attributes of the handler, in the order given.
+fn get_commands(attrs: &[Attribute], handler_name: &Ident) -> Result<Vec<Path>> {
+    let mut commands: Vec<Path> = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("handles")) {
+        let command = get_command(attr)?;
+        let name = quote!(#command).to_string();
+        if commands
+            .iter()
+            .any(|known| quote!(#known).to_string() == name)
+        {
+            let message = format!("duplicate #[handles = \"{}\"]", name.replace(' ', ""));
+            return Err(Error::new_spanned(attr, message));
+        }
+        commands.push(command);
+    }
 
-    if meta.name() != "handles" {
-        let message = "What would you like me to handle?";
-        return Err(Error::new(Span::call_site(), message));
+    if commands.is_empty() {
+        let message = format!(
+            "missing #[handles = \"...\"] naming the command {} handles",
+            handler_name
+        );
+        return Err(Error::new_spanned(handler_name, message));
     }
 
+    Ok(commands)
+}
+
+fn get_command(attr: &Attribute) -> Result<Path> {
+    let message = "expected #[handles = \"...\"]";
+    let meta = attr
+        .parse_meta()
+        .map_err(|_| Error::new_spanned(attr, message))?;
+
     match meta {
         Meta::NameValue(MetaNameValue {
             lit: Lit::Str(lit_str),
             ..
-        }) => lit_str.parse(),
-        _ => {
-            let error_span = attr.bracket_token.span;
-            let message = "expected #[handles = \"...\"]";
-            Err(Error::new(error_span, message))
+        }) => lit_str.parse().map_err(|_| {
+            let message = "expected the path of a command, e.g. #[handles = \"DepositMoney\"]";
+            Error::new_spanned(&lit_str, message)
+        }),
+        meta => Err(Error::new_spanned(meta, message)),
+    }
+}
+
+/// Method a handler of several commands carries out the given one with, e.g.
+/// `handle_deposit_money` for `DepositMoney`.
+fn method_for(command: &Path) -> Ident {
+    let command = &command.segments.last().unwrap().into_value().ident;
+    let mut method = String::from("handle");
+    for c in command.to_string().chars() {
+        if c.is_uppercase() {
+            method.push('_');
         }
+        method.extend(c.to_lowercase());
     }
+
+    Ident::new(&method, command.span())
 }
 
-#[proc_macro_derive(XbusCommand, attributes(handles))]
+#[proc_macro_derive(XbusCommand, attributes(handles, xbus))]
 pub fn add_command_message(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
+    let options = match get_options(&ast.attrs, &["result", "error"]) {
+        Ok(options) => options,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
     let command_name = ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let result = type_or_unit(options.result);
+    let error = type_or_unit(options.error);
 
     let expanded = quote! {
-        impl actix::Message for #command_name {
-            type Result = std::result::Result<(), ()>;
+        impl #impl_generics xbus::XbusCommand for #command_name #ty_generics #where_clause {
+            type Result = #result;
+            type Error = #error;
+        }
+
+        impl #impl_generics xbus::Message for #command_name #ty_generics #where_clause {
+            type Result = xbus::DispatchResult<Self>;
         }
     };
-    return TokenStream::from(expanded);
+    TokenStream::from(expanded)
+}
+
+/// Settings given as `#[xbus(result = "...", error = "...", bound = "...")]`.
+#[derive(Default)]
+struct Options {
+    /// Type the command results in, or the handler's own `handle` returns when it succeeds.
+    result: Option<Type>,
+    /// Type the command fails with, or the handler's own `handle` returns when it fails.
+    error: Option<Type>,
+    /// Where clause predicates the generated handler impl needs, e.g. `S: EventStore<A>`.
+    bound: Option<Punctuated<WherePredicate, Token![,]>>,
+}
+
+fn get_options(attrs: &[Attribute], allowed: &[&str]) -> Result<Options> {
+    let mut options = Options::default();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("xbus")) {
+        let nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            meta => {
+                let message = "expected #[xbus(result = \"...\", error = \"...\")]";
+                return Err(Error::new_spanned(meta, message));
+            }
+        };
+
+        for meta in nested {
+            let (name, value) = match meta {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ident,
+                    lit: Lit::Str(lit_str),
+                    ..
+                })) => (ident, lit_str),
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue { lit, .. })) => {
+                    return Err(Error::new_spanned(lit, "expected a string literal"));
+                }
+                meta => {
+                    let message = "expected `name = \"...\"`";
+                    return Err(Error::new_spanned(meta, message));
+                }
+            };
+
+            if name == "result" && allowed.contains(&"result") {
+                set_option(&mut options.result, &name, value.parse()?)?;
+            } else if name == "error" && allowed.contains(&"error") {
+                set_option(&mut options.error, &name, value.parse()?)?;
+            } else if name == "bound" && allowed.contains(&"bound") {
+                set_option(&mut options.bound, &name, parse_bound(&value)?)?;
+            } else {
+                let message = format!(
+                    "unknown xbus option `{}`, expected one of: {}",
+                    name,
+                    allowed.join(", ")
+                );
+                return Err(Error::new_spanned(name, message));
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn set_option<T>(option: &mut Option<T>, name: &Ident, value: T) -> Result<()> {
+    if option.is_some() {
+        let message = format!("duplicate xbus option `{}`", name);
+        return Err(Error::new_spanned(name, message));
+    }
+    *option = Some(value);
+    Ok(())
+}
+
+fn parse_bound(value: &LitStr) -> Result<Punctuated<WherePredicate, Token![,]>> {
+    value
+        .parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)
+        .map_err(|err| Error::new_spanned(value, format!("invalid bound: {}", err)))
+}
+
+fn type_or_unit(ty: Option<Type>) -> TokenStream2 {
+    match ty {
+        Some(ty) => quote!(#ty),
+        None => quote!(()),
+    }
+}
+
+/// Lets the compiler infer what the handler's own `handle` returns if it wasn't spelled out.
+fn type_or_inferred(ty: Option<Type>) -> TokenStream2 {
+    match ty {
+        Some(ty) => quote!(#ty),
+        None => quote!(_),
+    }
 }
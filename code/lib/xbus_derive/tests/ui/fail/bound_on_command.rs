@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(bound = "T: Clone")]
+struct Ping<T>(T);
+
+fn main() {}
@@ -0,0 +1,11 @@
+use xbus_derive::{XbusCommand, XbusCommandHandler};
+
+#[derive(XbusCommand)]
+struct Ping;
+
+#[derive(XbusCommandHandler)]
+#[handles = "Ping"]
+#[handles = "Ping"]
+struct PingHandler;
+
+fn main() {}
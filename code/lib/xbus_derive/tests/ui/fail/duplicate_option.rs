@@ -0,0 +1,8 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(error = "String")]
+#[xbus(error = "u32")]
+struct Ping;
+
+fn main() {}
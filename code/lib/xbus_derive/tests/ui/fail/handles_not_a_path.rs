@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommandHandler;
+
+#[derive(XbusCommandHandler)]
+#[handles = "Ping Pong"]
+struct PingHandler;
+
+fn main() {}
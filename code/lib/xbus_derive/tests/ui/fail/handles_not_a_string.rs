@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommandHandler;
+
+#[derive(XbusCommandHandler)]
+#[handles = 42]
+struct PingHandler;
+
+fn main() {}
@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommandHandler;
+
+#[derive(XbusCommandHandler)]
+#[handles]
+struct PingHandler;
+
+fn main() {}
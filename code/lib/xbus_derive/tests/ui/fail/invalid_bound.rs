@@ -0,0 +1,10 @@
+use xbus_derive::XbusCommandHandler;
+
+struct Ping;
+
+#[derive(XbusCommandHandler)]
+#[handles = "Ping"]
+#[xbus(bound = "T:: Clone")]
+struct PingHandler<T>(T);
+
+fn main() {}
@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(error = "not a type")]
+struct Ping;
+
+fn main() {}
@@ -0,0 +1,20 @@
+use xbus_derive::{XbusCommand, XbusCommandHandler};
+
+#[derive(XbusCommand)]
+struct Ping;
+
+#[derive(XbusCommand)]
+struct Pong;
+
+#[derive(XbusCommandHandler)]
+#[handles = "Ping"]
+#[handles = "Pong"]
+struct PingPongHandler;
+
+impl PingPongHandler {
+    fn handle_ping(&self, _command: Ping) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommandHandler;
+
+/// Forgot to say what it handles.
+#[derive(XbusCommandHandler)]
+struct PingHandler;
+
+fn main() {}
@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus = "u32"]
+struct Ping;
+
+fn main() {}
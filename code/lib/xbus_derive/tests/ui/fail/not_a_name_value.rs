@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(result)]
+struct Ping;
+
+fn main() {}
@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(result = 42)]
+struct Ping;
+
+fn main() {}
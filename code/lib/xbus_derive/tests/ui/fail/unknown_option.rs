@@ -0,0 +1,7 @@
+use xbus_derive::XbusCommand;
+
+#[derive(XbusCommand)]
+#[xbus(result = "u32", reply = "u32")]
+struct Ping;
+
+fn main() {}
@@ -0,0 +1,26 @@
+use std::fmt::Display;
+use xbus::CommandBus;
+use xbus_derive::{XbusCommand, XbusCommandHandler};
+
+#[derive(Clone, XbusCommand)]
+#[xbus(result = "String")]
+struct Greet;
+
+#[derive(XbusCommandHandler)]
+#[handles = "Greet"]
+#[xbus(bound = "T: Display + 'static")]
+struct GreetHandler<T> {
+    name: T,
+}
+
+impl<T: Display> GreetHandler<T> {
+    fn handle(&self, _command: Greet) -> Result<String, ()> {
+        Ok(format!("Hello {}", self.name))
+    }
+}
+
+fn main() {
+    let bus = CommandBus::new().register(GreetHandler { name: "Miro" });
+
+    assert_eq!(Ok("Hello Miro".to_string()), bus.dispatch(Greet));
+}
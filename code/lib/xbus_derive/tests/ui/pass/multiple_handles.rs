@@ -0,0 +1,43 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use xbus::CommandBus;
+use xbus_derive::{XbusCommand, XbusCommandHandler};
+
+#[derive(Clone, XbusCommand)]
+#[xbus(result = "i32")]
+struct Increment(i32);
+
+#[derive(Clone, XbusCommand)]
+#[xbus(result = "i32")]
+struct Decrement(i32);
+
+/// Keeps the count, the doc comment and the derive come before the handled commands.
+#[derive(Default, XbusCommandHandler)]
+#[handles = "Increment"]
+#[handles = "Decrement"]
+struct CountHandler {
+    count: Cell<i32>,
+}
+
+impl CountHandler {
+    fn handle_increment(&self, command: Increment) -> Result<i32, ()> {
+        self.count.set(self.count.get() + command.0);
+        Ok(self.count.get())
+    }
+
+    fn handle_decrement(&self, command: Decrement) -> Result<i32, ()> {
+        self.count.set(self.count.get() - command.0);
+        Ok(self.count.get())
+    }
+}
+
+fn main() {
+    let handler = Rc::new(CountHandler::default());
+    let bus = CommandBus::new()
+        .register::<Increment, _>(Rc::clone(&handler))
+        .register::<Decrement, _>(Rc::clone(&handler));
+
+    assert_eq!(Ok(5), bus.dispatch(Increment(5)));
+    assert_eq!(Ok(3), bus.dispatch(Decrement(2)));
+    assert_eq!(3, handler.count.get());
+}
@@ -0,0 +1,27 @@
+use xbus::{CommandBus, DispatchError};
+use xbus_derive::{XbusCommand, XbusCommandHandler};
+
+#[derive(Clone, XbusCommand)]
+#[xbus(result = "u32", error = "String")]
+struct Double(u8);
+
+#[derive(XbusCommandHandler)]
+#[handles = "Double"]
+#[xbus(result = "u8", error = "&'static str")]
+struct DoubleHandler;
+
+impl DoubleHandler {
+    fn handle(&self, command: Double) -> Result<u8, &'static str> {
+        command.0.checked_mul(2).ok_or("too big")
+    }
+}
+
+fn main() {
+    let bus = CommandBus::new().register(DoubleHandler);
+
+    assert_eq!(Ok(42), bus.dispatch(Double(21)));
+    assert_eq!(
+        Err(DispatchError::Handler("too big".to_string())),
+        bus.dispatch(Double(200))
+    );
+}